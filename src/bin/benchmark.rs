@@ -9,12 +9,38 @@ extern crate zipf;
 use ccl::dhashmap::DHashMap;
 use chashmap::CHashMap;
 use clap::{App, Arg};
+use concache::ConcurrentMap;
 use rand::distributions::Distribution;
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::collections::HashMap;
 use std::sync;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 use std::time;
 
+/// An allocator that keeps track of how many bytes are in use, and the most that ever were, so
+/// that the memory footprint of the maps can be compared.
+struct Footprint;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Footprint {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let in_use = IN_USE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(in_use, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Footprint = Footprint;
+
 fn main() {
     let matches = App::new("Concurrent HashMap Benchmarker")
         .version(crate_version!())
@@ -63,6 +89,23 @@ fn main() {
     let dur_in_s = dur_in_ns as f64 / 1_000_000_000_f64;
     let span = 10000;
 
+    // the peak footprint is measured relative to what was in use before the map was created
+    let start_footprint = || {
+        let in_use = IN_USE.load(Ordering::Relaxed);
+        PEAK.store(in_use, Ordering::Relaxed);
+        in_use
+    };
+    let footprint = |var: &str, base: usize| {
+        println!(
+            "{:2} {:2} {:10} {:10} {:8} bytes peak",
+            readers,
+            writers,
+            dist,
+            var,
+            PEAK.load(Ordering::Relaxed) - base
+        )
+    };
+
     let stat = |var: &str, op, results: Vec<(_, usize)>| {
         for (i, res) in results.into_iter().enumerate() {
             println!(
@@ -153,7 +196,8 @@ fn main() {
 
     // benchmark concache::manual
     {
-        let map = concache::manual::Map::with_capacity(5_000_000);
+        let base = start_footprint();
+        let map = Concache(concache::manual::Map::with_capacity(5_000_000));
         let start = time::Instant::now();
         let end = start + dur;
         join.extend((0..readers).map(|_| {
@@ -172,6 +216,57 @@ fn main() {
             .partition(|&(write, _)| write);
         stat("concache::manual", "write", wres);
         stat("concache::manual", "read", rres);
+        footprint("concache::manual", base);
+    }
+
+    // benchmark concache::crossbeam
+    {
+        let base = start_footprint();
+        let map = Concache(concache::crossbeam::Map::with_capacity(5_000_000));
+        let start = time::Instant::now();
+        let end = start + dur;
+        join.extend((0..readers).map(|_| {
+            let map = map.clone();
+            let dist = dist.to_owned();
+            thread::spawn(move || drive(map, end, &dist, false, span))
+        }));
+        join.extend((0..writers).map(|_| {
+            let map = map.clone();
+            let dist = dist.to_owned();
+            thread::spawn(move || drive(map, end, &dist, true, span))
+        }));
+        let (wres, rres): (Vec<_>, _) = join
+            .drain(..)
+            .map(|jh| jh.join().unwrap())
+            .partition(|&(write, _)| write);
+        stat("concache::crossbeam", "write", wres);
+        stat("concache::crossbeam", "read", rres);
+        footprint("concache::crossbeam", base);
+    }
+
+    // benchmark concache::hazard
+    {
+        let base = start_footprint();
+        let map = Concache(concache::hazard::Map::with_capacity(5_000_000));
+        let start = time::Instant::now();
+        let end = start + dur;
+        join.extend((0..readers).map(|_| {
+            let map = map.clone();
+            let dist = dist.to_owned();
+            thread::spawn(move || drive(map, end, &dist, false, span))
+        }));
+        join.extend((0..writers).map(|_| {
+            let map = map.clone();
+            let dist = dist.to_owned();
+            thread::spawn(move || drive(map, end, &dist, true, span))
+        }));
+        let (wres, rres): (Vec<_>, _) = join
+            .drain(..)
+            .map(|jh| jh.join().unwrap())
+            .partition(|&(write, _)| write);
+        stat("concache::hazard", "write", wres);
+        stat("concache::hazard", "read", rres);
+        footprint("concache::hazard", base);
     }
 }
 
@@ -243,13 +338,17 @@ impl Backend for sync::Arc<DHashMap<usize, usize>> {
     }
 }
 
-impl Backend for concache::manual::MapHandle<usize, usize> {
+/// Any of the concache maps, driven through the `ConcurrentMap` trait.
+#[derive(Clone)]
+struct Concache<M>(M);
+
+impl<M: ConcurrentMap<usize, usize>> Backend for Concache<M> {
     fn b_get(&mut self, key: usize) -> usize {
-        self.get(&(key as usize)).unwrap_or(0) as usize
+        self.0.get(&key).unwrap_or(0)
     }
 
     fn b_put(&mut self, key: usize, value: usize) {
-        self.insert(key as usize, value as usize);
+        self.0.insert(key, value);
     }
 }
 
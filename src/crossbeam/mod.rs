@@ -4,9 +4,14 @@
 //! This implementation provides a lock-free hash map using buckets that hold [lock-free linked
 //! lists](https://www.microsoft.com/en-us/research/wp-content/uploads/2001/10/2001-disc.pdf).
 //! Memory is safely destructed and reclaimed using
-//! [`crossbeam::epoch`](https://docs.rs/crossbeam-epoch/). Table resizing is not yet supported,
-//! but the map will also never fill due to the linked implementation; instead, performance will
-//! decrease as the map is filled with more keys.
+//! [`crossbeam::epoch`](https://docs.rs/crossbeam-epoch/).
+//!
+//! All keys are kept in a single list ordered by their bit-reversed hashes, and buckets are
+//! shortcuts into that list ([split-ordered lists](https://dl.acm.org/citation.cfm?id=1147958)).
+//! When the average number of keys per bucket grows too large, the number of buckets doubles, and
+//! each new bucket is split off its parent the first time an operation touches it. Nodes never
+//! move while the table grows, and replaced bucket directories are reclaimed through the epoch
+//! collector, so the map can start small and grow to hold any number of keys.
 //!
 //! The interface to this map is somewhat different from `HashMap` to support concurrent operation.
 //! When you create a new [`Map`],you are given a [`MapHandle`], which allows access to the map's
@@ -17,57 +22,285 @@
 //! guarantee that destructors are called. In practice though, as long as threads do not leak
 //! `MapHandle`s, destructors will all eventually be called.
 //!
-//! Note that unlike `HashMap`, this `Map` requires its values to be `Copy`. This greatly
-//! simplifies the map's interface; accesses to the map's data have to be carefully guarded, and
-//! there is no simple way to expose references into the map through a method call. Later, we may
-//! provide temporary access through closures, similar to `evmap`'s
-//! [`ReadHandle::get_and`](https://docs.rs/evmap/4/evmap/struct.ReadHandle.html#method.get_and),
-//! but for the time being, values have to be `Copy`.
+//! Note that unlike `HashMap`, this `Map` cannot hand out references into the map through a method
+//! call, since accesses to the map's data have to be guarded by an epoch. Instead, values can be
+//! read by copying them out with [`MapHandle::get`], by cloning them with
+//! [`MapHandle::get_cloned`], or through a closure that is given temporary access to the value,
+//! similar to `evmap`'s
+//! [`ReadHandle::get_and`](https://docs.rs/evmap/4/evmap/struct.ReadHandle.html#method.get_and).
+//! Old values are dropped once no thread can be accessing them anymore.
 
-mod linked_list;
+pub use epoch::{pin, Guard};
 
-use self::linked_list::LinkedList;
-use std::collections::hash_map::DefaultHasher;
+use reclaim::{self, Reclaimer};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::hash::{Hash, Hasher};
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
-};
+use std::hash::{BuildHasher, Hash};
+use std::ptr::NonNull;
+use std::sync::atomic::{self, AtomicPtr, AtomicU64, Ordering};
+use std::sync::Arc;
+use table::{self, Buckets, Cursor, Node, Table};
+use {ConcurrentMap, LoadReport, Stats};
+
+/// The number of nodes and values of a map that have been handed to the epoch collector, but
+/// have not been freed yet.
+///
+/// The collector may get to the garbage after the map is gone, so the counts are owned by the
+/// map and by every node and value that is waiting for the collector, through a [`Share`] each.
+/// The counts double as the count of shares, so a retirement only ever touches one counter: the
+/// nodes are counted in the low 32 bits, the values in the bits above them, and the top bit is set
+/// for as long as the map holds its share.
+struct Retired {
+    counts: AtomicU64,
+}
+
+const NODE: u64 = 1;
+const VALUE: u64 = 1 << 32;
+const MAP: u64 = 1 << 63;
+
+/// A share in the [`Retired`] counts of a map, which counts as `one` towards them. Whoever drops
+/// the last share frees the counts.
+struct Share {
+    retired: NonNull<Retired>,
+    one: u64,
+}
+
+// the counts are only ever accessed atomically
+unsafe impl Send for Share {}
+unsafe impl Sync for Share {}
+
+impl Share {
+    /// Returns the share of a new map, with nothing retired yet.
+    fn new() -> Self {
+        let retired = Box::new(Retired {
+            counts: AtomicU64::new(MAP),
+        });
+        Share {
+            retired: NonNull::from(Box::leak(retired)),
+            one: MAP,
+        }
+    }
+
+    /// Counts one more retired node or value, and returns its share.
+    fn add(&self, one: u64) -> Share {
+        self.counts().fetch_add(one, Ordering::Relaxed);
+        Share {
+            retired: self.retired,
+            one,
+        }
+    }
+
+    /// Returns the number of retired nodes and values.
+    fn load(&self) -> (usize, usize) {
+        let counts = self.counts().load(Ordering::Relaxed) & !MAP;
+        ((counts % VALUE) as usize, (counts / VALUE) as usize)
+    }
+
+    fn counts(&self) -> &AtomicU64 {
+        unsafe { &self.retired.as_ref().counts }
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        // like the last `Arc`, the last share has to see everything the other shares did
+        if self.counts().fetch_sub(self.one, Ordering::Release) == self.one {
+            atomic::fence(Ordering::Acquire);
+            drop(unsafe { Box::from_raw(self.retired.as_ptr()) });
+        }
+    }
+}
+
+/// Hands `ptr` to the epoch collector, to be freed once no pinned thread can be reading it. It
+/// counts as `one` towards the map's `retired` counts until then.
+unsafe fn defer_free<T>(guard: &Guard, ptr: *mut T, retired: &Share, one: u64) {
+    let share = retired.add(one);
+    guard.defer_unchecked(move || {
+        drop(Box::from_raw(ptr));
+        drop(share);
+    });
+}
+
+/// Panics unless `guard` pins the default collector, which is the collector [`pin`] uses and the
+/// one all garbage of the map is deferred to. A guard of any other collector does not keep the
+/// map's memory alive, so nothing may be read from the map under it.
+fn check_guard(guard: &Guard) {
+    assert!(
+        guard.collector() == Some(epoch::default_collector()),
+        "the guard must come from `concache::crossbeam::pin`"
+    );
+}
+
+/// A thread operating on a map while pinned by `guard`.
+struct Pinned<'g> {
+    guard: &'g Guard,
+    retired: &'g Share,
+}
+
+// a pinned thread keeps everything that it could reach alive until it unpins, so nothing it loads
+// has to be protected, and garbage can go straight to the epoch collector. the collector is
+// global, so like those passed to `Guard::defer`, the garbage may be dropped on any thread, and
+// after the map itself is gone.
+impl<'g, K, V> Reclaimer<K, V> for Pinned<'g>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    // the guard is the critical section, and it is held for as long as the `Pinned` is alive
+    fn enter(&mut self) {}
+
+    fn exit(&mut self) {}
+
+    fn protect<T>(&mut self, _: usize, _: *mut T) {}
+
+    fn load<T>(&mut self, _: usize, src: &AtomicPtr<T>) -> *mut T {
+        src.load(Ordering::SeqCst)
+    }
+
+    fn retire_node(&mut self, node: *mut Node<K, V>) {
+        unsafe { defer_free(self.guard, node, self.retired, NODE) };
+    }
+
+    fn retire_value(&mut self, value: *mut V) {
+        unsafe { defer_free(self.guard, value, self.retired, VALUE) };
+    }
+
+    fn retire_buckets(&mut self, buckets: *mut Buckets<K, V>) {
+        unsafe {
+            self.guard
+                .defer_unchecked(move || drop(Box::from_raw(buckets)))
+        };
+    }
+}
+
+/// The state shared by all handles to a map.
+struct Inner<K, V, S> {
+    table: Table<K, V>,
+    hash_builder: S,
+    retired: Share,
+}
+
+impl<K, V, S> Inner<K, V, S> {
+    fn pinned<'g>(&'g self, guard: &'g Guard) -> Pinned<'g> {
+        Pinned {
+            guard,
+            retired: &self.retired,
+        }
+    }
+
+    /// Runs a single operation `f` on the map's table, while pinned by `guard`.
+    fn operate<'g, F, R>(&'g self, guard: &'g Guard, f: F) -> R
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+        F: FnOnce(&'g Table<K, V>, &mut Pinned<'g>) -> R,
+    {
+        let mut pinned = self.pinned(guard);
+        reclaim::critical::<K, V, _, _, _>(&mut pinned, |pinned| f(&self.table, pinned))
+    }
+}
+
+impl<K, V, S> Inner<K, V, S>
+where
+    S: BuildHasher,
+{
+    fn hash<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Hash,
+    {
+        table::hash(&self.hash_builder, key)
+    }
+}
 
 /// A handle to a shared [`Map`].
 ///
 /// Any operation performed on this handle affects the map seen by all other related `MapHandle`
 /// instances. To get another handle to the `Map`, simply clone any of its handles.
-#[derive(Clone)]
-pub struct MapHandle<K, V> {
-    bsize: usize,
-    size: Arc<AtomicUsize>,
-    mp: Arc<Vec<LinkedList<K, V>>>,
+///
+/// Handles can be sent to and shared with other threads if the keys and values can be. Memory
+/// that is removed from the map is freed by the global epoch collector, which may do so on any
+/// thread, and only after the map itself is gone, so operations also require the keys and values
+/// to be `Send + 'static`.
+///
+/// A map of values that cannot be sent to another thread, such as `Rc`, stays on its thread:
+///
+/// ```compile_fail
+/// use concache::crossbeam::Map;
+/// use std::rc::Rc;
+/// use std::thread;
+///
+/// let map: Map<u8, Rc<u8>> = Map::with_capacity(16);
+/// thread::spawn(move || map.len());
+/// ```
+///
+/// A map of values that cannot be shared between threads, such as `Cell`, cannot be shared
+/// either:
+///
+/// ```compile_fail
+/// use concache::crossbeam::Map;
+/// use std::cell::Cell;
+/// use std::thread;
+///
+/// let map: Map<u8, Cell<u8>> = Map::with_capacity(16);
+/// let other = map.clone();
+/// thread::spawn(move || other.len());
+/// ```
+pub struct MapHandle<K, V, S = RandomState> {
+    inner: Arc<Inner<K, V, S>>,
+}
+
+impl<K, V, S> Clone for MapHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        MapHandle {
+            inner: Arc::clone(&self.inner),
+        }
+    }
 }
 
 /// A shared, concurrent hash map.
 ///
 /// See [`MapHandle`] for how to interact with this map.
-pub type Map<K, V> = MapHandle<K, V>;
+pub type Map<K, V, S = RandomState> = MapHandle<K, V, S>;
 
-impl<K, V> MapHandle<K, V> {
+impl<K, V> MapHandle<K, V, RandomState> {
     /// Create a new, shared map and return a handle to it.
     ///
-    /// The map will have `nbuckets` buckets to distribute stored keys among. If there are many
-    /// more keys than buckets, performance will suffer, as all the keys in a key's bucket must be
-    /// searched to read or update that key.
+    /// The map will initially have `nbuckets` buckets (rounded up to a power of two) to distribute
+    /// stored keys among. The number of buckets doubles whenever the map holds more than two keys
+    /// per bucket on average, so `nbuckets` only needs to be a rough estimate.
+    ///
+    /// Keys are hashed with a [`RandomState`] that is seeded differently for every map, which
+    /// makes it hard for an attacker to pick keys that all end up in the same bucket.
     pub fn with_capacity(nbuckets: usize) -> Self {
-        let mut v = Vec::with_capacity(nbuckets);
-
-        for _i in 0..nbuckets {
-            v.push(LinkedList::default());
-        }
+        Self::with_capacity_and_hasher(nbuckets, RandomState::new())
+    }
+}
 
+impl<K, V, S> MapHandle<K, V, S> {
+    /// Create a new, shared map that uses `hash_builder` to hash keys, and return a handle to it.
+    ///
+    /// See [`MapHandle::with_capacity`] for the meaning of `nbuckets`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    ///
+    /// let hasher = BuildHasherDefault::<DefaultHasher>::default();
+    /// let map = Map::with_capacity_and_hasher(16, hasher);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some("a"));
+    /// ```
+    pub fn with_capacity_and_hasher(nbuckets: usize, hash_builder: S) -> Self {
         Map {
-            bsize: nbuckets,
-            size: Arc::new(AtomicUsize::new(0)),
-            mp: Arc::new(v),
+            inner: Arc::new(Inner {
+                table: Table::new(nbuckets),
+                hash_builder,
+                retired: Share::new(),
+            }),
         }
     }
 
@@ -84,7 +317,7 @@ impl<K, V> MapHandle<K, V> {
     /// assert_eq!(a.len(), 1);
     /// ```
     pub fn len(&self) -> usize {
-        self.size.load(Ordering::SeqCst)
+        self.inner.table.len()
     }
 
     /// Returns true if the map contains no elements.
@@ -100,22 +333,294 @@ impl<K, V> MapHandle<K, V> {
     /// assert!(!a.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.size.load(Ordering::SeqCst) == 0
+        self.inner.table.len() == 0
+    }
+
+    /// Returns statistics about the memory the map is holding on to.
+    ///
+    /// Garbage is freed by `crossbeam::epoch` on behalf of all threads, so the retired nodes and
+    /// values are those of the whole map, and are only freed as the epoch collector gets to them.
+    /// Since the collector never waits for other threads and runs on its own schedule,
+    /// `cleanups` and `wait_time` are always zero. Every clone of a handle counts towards
+    /// `handles`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.remove(&1);
+    ///
+    /// let stats = map.stats();
+    /// assert_eq!(stats.handles, 1);
+    /// assert!(stats.retired_values <= 1);
+    /// assert_eq!(stats.live_bytes, 0);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let (retired_nodes, retired_values) = self.inner.retired.load();
+        Stats {
+            retired_nodes,
+            retired_values,
+            handles: Arc::strong_count(&self.inner),
+            live_bytes: self.inner.table.live_bytes(),
+            ..Stats::default()
+        }
+    }
+
+    /// Walks the whole map, and reports how its keys are spread over its buckets.
+    ///
+    /// This visits every node in the map, and keeps the current thread pinned while it does. The
+    /// report is not a consistent snapshot: keys that are inserted or removed during the walk may
+    /// or may not be counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// for i in 0..10 {
+    ///     map.insert(i, i);
+    /// }
+    ///
+    /// let report = map.load_report();
+    /// assert_eq!(report.buckets, 16);
+    /// assert_eq!(report.histogram.iter().sum::<usize>(), 16);
+    /// assert_eq!(report.deleted_nodes, 0);
+    /// ```
+    pub fn load_report(&self) -> LoadReport
+    where
+        K: Send + 'static,
+        V: Send + 'static,
+    {
+        let guard = pin();
+        self.inner.operate(&guard, |table, r| table.load_report(r))
+    }
+}
+
+impl<K, V, S> MapHandle<K, V, S>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    /// An iterator visiting all key-value pairs in the map.
+    ///
+    /// The iterator is weakly consistent: every key that is in the map for the entire iteration is
+    /// yielded exactly once, while keys that are inserted or removed concurrently may or may not
+    /// be yielded. Keys are yielded in no particular order. The yielded references are valid for
+    /// as long as `guard` is held.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` does not come from [`pin`], since the guard of another collector does not
+    /// keep the entries alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::{pin, Map};
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let guard = pin();
+    /// let mut entries: Vec<_> = map.iter(&guard).collect();
+    /// entries.sort();
+    /// assert_eq!(entries, vec![(&1, &"a"), (&2, &"b")]);
+    /// ```
+    pub fn iter<'g>(&'g self, guard: &'g Guard) -> Iter<'g, K, V> {
+        check_guard(guard);
+        Iter {
+            table: &self.inner.table,
+            cursor: Cursor::new(&self.inner.table),
+            pinned: self.inner.pinned(guard),
+        }
+    }
+
+    /// An iterator visiting clones of all key-value pairs in the map.
+    ///
+    /// Unlike [`MapHandle::iter`], the iterator pins the current thread itself, and keeps it
+    /// pinned until the iterator is dropped. It has the same consistency guarantees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut entries: Vec<_> = map.iter_cloned().collect();
+    /// entries.sort();
+    /// assert_eq!(entries, vec![(1, "a"), (2, "b")]);
+    /// ```
+    pub fn iter_cloned(&self) -> IterCloned<'_, K, V> {
+        IterCloned {
+            table: &self.inner.table,
+            cursor: Cursor::new(&self.inner.table),
+            guard: pin(),
+            retired: &self.inner.retired,
+        }
+    }
+
+    /// An iterator visiting all keys in the map.
+    ///
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator, and for which
+    /// guards it accepts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::{pin, Map};
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let guard = pin();
+    /// let mut keys: Vec<_> = map.keys(&guard).collect();
+    /// keys.sort();
+    /// assert_eq!(keys, vec![&1, &2]);
+    /// ```
+    pub fn keys<'g>(&'g self, guard: &'g Guard) -> Keys<'g, K, V> {
+        Keys {
+            inner: self.iter(guard),
+        }
+    }
+
+    /// An iterator visiting all values in the map.
+    ///
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator, and for which
+    /// guards it accepts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::{pin, Map};
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let guard = pin();
+    /// let mut values: Vec<_> = map.values(&guard).collect();
+    /// values.sort();
+    /// assert_eq!(values, vec![&"a", &"b"]);
+    /// ```
+    pub fn values<'g>(&'g self, guard: &'g Guard) -> Values<'g, K, V> {
+        Values {
+            inner: self.iter(guard),
+        }
+    }
+}
+
+/// An iterator over the keys of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::keys`]. See its documentation for more.
+pub struct Keys<'g, K: 'g, V: 'g> {
+    inner: Iter<'g, K, V>,
+}
+
+impl<'g, K, V> Iterator for Keys<'g, K, V>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    type Item = &'g K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
+
+/// An iterator over the values of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::values`]. See its documentation for more.
+pub struct Values<'g, K: 'g, V: 'g> {
+    inner: Iter<'g, K, V>,
+}
+
+impl<'g, K, V> Iterator for Values<'g, K, V>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    type Item = &'g V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+}
+
+/// An iterator over the entries of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::iter`]. See its documentation for more.
+pub struct Iter<'g, K: 'g, V: 'g> {
+    table: &'g Table<K, V>,
+    cursor: Cursor<K, V>,
+    pinned: Pinned<'g>,
+}
+
+impl<'g, K, V> Iterator for Iter<'g, K, V>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    type Item = (&'g K, &'g V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // everything the cursor passes stays alive for as long as the guard is held
+        unsafe { self.cursor.next(self.table, &mut self.pinned) }
+    }
+}
+
+/// An iterator over clones of the entries of a [`Map`], which pins the current thread for as long
+/// as it is alive.
+///
+/// This `struct` is created by [`MapHandle::iter_cloned`]. See its documentation for more.
+pub struct IterCloned<'a, K: 'a, V: 'a> {
+    table: &'a Table<K, V>,
+    cursor: Cursor<K, V>,
+    guard: Guard,
+    retired: &'a Share,
+}
+
+impl<'a, K, V> Iterator for IterCloned<'a, K, V>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the node the cursor is at stays protected by our own guard, so we can resume from it on
+        // every call
+        let mut pinned = Pinned {
+            guard: &self.guard,
+            retired: self.retired,
+        };
+        let entry = unsafe { self.cursor.next(self.table, &mut pinned) };
+        entry.map(|(k, v)| (k.clone(), v.clone()))
     }
 }
 
-impl<K, V> Map<K, V>
+impl<K, V, S> Map<K, V, S>
 where
-    K: Eq + Hash,
-    V: Copy,
+    K: Eq + Hash + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher,
 {
     /// Inserts a key-value pair into the map.
     ///
-    /// If the map did not have this key present, `true` is returned.
+    /// If the map did not have this key present, `None` is returned.
     ///
-    /// If the map did have this key present, the value is updated, and `false` is returned.
-    /// The key is not updated, though; this matters for types that can be `==` without being
-    /// identical.
+    /// If the map did have this key present, the value is updated, and a clone of the old value is
+    /// returned. The key is not updated, though; this matters for types that can be `==` without
+    /// being identical.
     ///
     /// # Examples
     ///
@@ -130,24 +635,142 @@ where
     /// assert_eq!(map.insert(37, "c"), Some("b"));
     /// assert_eq!(map.get(&37), Some("c"));
     /// ```
-    pub fn insert(&self, key: K, value: V) -> Option<V> {
-        let mut hsh = DefaultHasher::new();
-        key.hash(&mut hsh);
-        let h = hsh.finish() as usize;
+    pub fn insert(&self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let guard = pin();
+        let hash = self.inner.hash(&key);
 
-        let ndx = h % self.bsize;
-        let ret = self.mp[ndx].insert((key, value));
+        self.inner
+            .operate(&guard, |table, r| table.insert(r, hash, key, value))
+            .map(|v| unsafe { &*v }.clone())
+    }
 
-        return match ret {
-            Some(v) => Some(unsafe { *v }),
-            None => {
-                self.size.fetch_add(1, Ordering::SeqCst);
-                None
-            }
-        };
+    /// Inserts a key-value pair into the map only if the key is not already present.
+    ///
+    /// Returns `Ok(())` if the value was inserted, and gives the value back as `Err(value)` if the
+    /// key was already present. Of several threads racing to insert the same key, exactly one
+    /// succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// assert_eq!(map.try_insert(37, "a"), Ok(()));
+    /// assert_eq!(map.try_insert(37, "b"), Err("b"));
+    /// assert_eq!(map.get(&37), Some("a"));
+    /// ```
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), V> {
+        let mut value = Some(value);
+
+        let guard = pin();
+        match self.insert_new(key, || value.take().unwrap(), &guard) {
+            Ok(_) => Ok(()),
+            Err((_, unused)) => Err(unused.or(value).unwrap()),
+        }
     }
 
-    /// Returns a reference to the value corresponding to the key.
+    /// Returns a clone of the value corresponding to the key, first inserting `value` if the key
+    /// is not already present.
+    ///
+    /// Of several threads racing to insert the same key, exactly one succeeds, and all of them
+    /// return the value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert(37, "a"), "a");
+    /// assert_eq!(map.get_or_insert(37, "b"), "a");
+    /// ```
+    pub fn get_or_insert(&self, key: K, value: V) -> V
+    where
+        V: Clone,
+    {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting the value returned
+    /// by `make` if the key is not already present.
+    ///
+    /// `make` is only called if the key is not present, but if another thread inserts the same key
+    /// concurrently, the value it returned may be dropped without ever being inserted. Of several
+    /// threads racing to insert the same key, exactly one succeeds, and all of them return the
+    /// value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert_with(37, || String::from("a")), "a");
+    /// assert_eq!(map.get_or_insert_with(37, || unreachable!()), "a");
+    /// ```
+    pub fn get_or_insert_with<F>(&self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        let guard = pin();
+        match self.insert_new(key, make, &guard) {
+            Ok(v) | Err((v, _)) => v.clone(),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn insert_new<'g, F>(
+        &'g self,
+        key: K,
+        make: F,
+        guard: &'g Guard,
+    ) -> Result<&'g V, (&'g V, Option<V>)>
+    where
+        F: FnOnce() -> V,
+    {
+        let hash = self.inner.hash(&key);
+
+        match self
+            .inner
+            .operate(guard, |table, r| table.insert_new(r, hash, key, make))
+        {
+            Ok(v) => Ok(unsafe { &*v }),
+            Err((v, unused)) => Err((unsafe { &*v }, unused)),
+        }
+    }
+
+    /// Returns `true` if the map contains a value for the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(String::from("a"), 1);
+    /// assert!(map.contains_key("a"));
+    /// assert!(!map.contains_key("b"));
+    /// ```
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_and(key, |_| ()).is_some()
+    }
+
+    /// Returns a copy of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
     ///
     /// # Examples
     ///
@@ -159,21 +782,236 @@ where
     /// assert_eq!(map.get(&1), Some("a"));
     /// assert_eq!(map.get(&2), None);
     /// ```
-    pub fn get(&self, key: &K) -> Option<V> {
-        let mut hsh = DefaultHasher::new();
-        key.hash(&mut hsh);
-        let h = hsh.finish() as usize;
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Copy,
+    {
+        self.get_and(key, |v| *v)
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, String::from("a"));
+    /// assert_eq!(map.get_cloned(&1), Some(String::from("a")));
+    /// assert_eq!(map.get_cloned(&2), None);
+    /// ```
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.get_and(key, V::clone)
+    }
+
+    /// Applies a function to the value corresponding to the key, and returns the result.
+    ///
+    /// The function is called while the current thread is pinned, so the value cannot be freed
+    /// while the function is accessing it. Long-running functions delay memory reclamation for
+    /// all threads.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, vec![1, 2, 3]);
+    /// assert_eq!(map.get_and(&1, |v| v.len()), Some(3));
+    /// assert_eq!(map.get_and(&2, |v| v.len()), None);
+    /// ```
+    pub fn get_and<Q, F, R>(&self, key: &Q, then: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        let guard = pin();
+        self.get_ref(key, &guard).map(then)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// The reference is valid for as long as `guard` is held, even if the key is removed or its
+    /// value replaced in the meantime. Pinning is comparatively expensive, so pinning once with
+    /// [`pin`] and reading many values under the same guard is faster than calling
+    /// [`MapHandle::get_and`] for each of them. Holding a guard for long prevents memory from being
+    /// reclaimed for all threads, though.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` does not come from [`pin`], since the guard of another collector does not
+    /// keep the value alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::{pin, Map};
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, String::from("a"));
+    /// map.insert(2, String::from("b"));
+    ///
+    /// let guard = pin();
+    /// let a = map.get_ref(&1, &guard).unwrap();
+    /// map.remove(&1);
+    /// assert_eq!(a, "a");
+    /// assert_eq!(map.get_ref(&2, &guard).map(String::as_str), Some("b"));
+    /// assert_eq!(map.get_ref(&3, &guard), None);
+    /// ```
+    pub fn get_ref<'g, Q>(&'g self, key: &Q, guard: &'g Guard) -> Option<&'g V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        check_guard(guard);
+        let hash = self.inner.hash(key);
+
+        self.inner
+            .operate(guard, |table, r| table.get(r, hash, key))
+            .map(|v| unsafe { &*v })
+    }
+
+    /// Atomically replaces the value corresponding to the key with the result of applying `f` to
+    /// it, and returns a clone of the new value. Returns `None`, without calling `f`, if the key is
+    /// not present.
+    ///
+    /// If another thread changes the value after `f` has read it, `f` is called again with the new
+    /// value, so `f` may be called several times and should not have side effects.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.update(&1, |v| v + 1), Some(11));
+    /// assert_eq!(map.update(&2, |v| v + 1), None);
+    /// ```
+    pub fn update<Q, F>(&self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> V,
+        V: Clone,
+    {
+        let guard = pin();
+        let hash = self.inner.hash(key);
+
+        self.inner
+            .operate(&guard, |table, r| {
+                table.update(r, hash, key, |v| Some(f(v)))
+            })
+            .ok()
+            .map(|v| unsafe { &*v }.clone())
+    }
+
+    /// Atomically replaces the value corresponding to the key with `new`, but only if the current
+    /// value is equal to `expected`.
+    ///
+    /// Returns `Ok(())` if the value was replaced. Otherwise, returns a clone of the current value,
+    /// or `None` if the key is not present.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.compare_exchange(&1, &"a", "b"), Ok(()));
+    /// assert_eq!(map.compare_exchange(&1, &"a", "c"), Err(Some("b")));
+    /// assert_eq!(map.compare_exchange(&2, &"a", "c"), Err(None));
+    /// ```
+    pub fn compare_exchange<Q>(&self, key: &Q, expected: &V, new: V) -> Result<(), Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        let guard = pin();
+        let hash = self.inner.hash(key);
 
-        let ndx = h % self.bsize;
+        let f = |v: &V| {
+            if v == expected {
+                Some(new.clone())
+            } else {
+                None
+            }
+        };
+        match self
+            .inner
+            .operate(&guard, |table, r| table.update(r, hash, key, f))
+        {
+            Ok(_) => Ok(()),
+            Err(current) => Err(current.map(|v| unsafe { &*v }.clone())),
+        }
+    }
+
+    /// Atomically sets the value corresponding to the key to the result of applying `f` to the
+    /// current value, or to `None` if the key is not present, and returns a clone of the new
+    /// value.
+    ///
+    /// If `f` returns `None`, the key is removed from the map. If the key was not present and `f`
+    /// returns `Some`, the key is inserted. As with [`MapHandle::update`], `f` may be called several
+    /// times if other threads modify the key concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(0));
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(1));
+    /// assert_eq!(map.compute(1, |_| None), None);
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn compute<F>(&self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+        V: Clone,
+    {
+        let guard = pin();
+        let hash = self.inner.hash(&key);
 
-        self.mp[ndx].get(key)
+        self.inner
+            .operate(&guard, |table, r| table.compute(r, hash, key, f))
+            .map(|v| unsafe { &*v }.clone())
     }
 
     /// Removes a key from the map, returning `true` if the key was previously in the map.
     ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
     /// # Examples
     ///
-    /// ```no_run
+    /// ```
     /// use concache::crossbeam::Map;
     ///
     /// let mut map = Map::with_capacity(16);
@@ -181,35 +1019,149 @@ where
     /// assert_eq!(map.remove(&1), true);
     /// assert_eq!(map.remove(&1), false);
     /// ```
-    pub fn remove(&self, key: &K) -> bool {
-        let mut hsh = DefaultHasher::new();
-        key.hash(&mut hsh);
-        let h = hsh.finish() as usize;
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.remove_if(key, |_| true)
+    }
 
-        let ndx = h % self.bsize;
+    /// Removes a key from the map if `pred` returns `true` for its value, returning `true` if the
+    /// key was removed.
+    ///
+    /// The check and the removal happen atomically: the key is only removed if its value has not
+    /// changed since `pred` was called. If the value did change, `pred` is called again with the
+    /// new value.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.remove_if(&1, |v| *v > 10), false);
+    /// assert_eq!(map.remove_if(&1, |v| *v == 10), true);
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn remove_if<Q, F>(&self, key: &Q, pred: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> bool,
+    {
+        self.remove_and(key, pred, |_| ()).is_some()
+    }
 
-        if self.mp[ndx].remove(key) {
-            self.size.fetch_sub(1, Ordering::SeqCst);
-            return true;
-        }
-        false
+    /// Removes a key from the map if its value is equal to `expected`, returning `true` if the key
+    /// was removed.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::crossbeam::Map;
+    ///
+    /// let map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove_if_eq(&1, &"b"), false);
+    /// assert_eq!(map.remove_if_eq(&1, &"a"), true);
+    /// ```
+    pub fn remove_if_eq<Q>(&self, key: &Q, expected: &V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: PartialEq,
+    {
+        self.remove_if(key, |v| v == expected)
+    }
+
+    /// Removes a key from the map if `pred` returns `true` for its value, and returns the result
+    /// of applying `then` to the removed value.
+    fn remove_and<Q, F, T, R>(&self, key: &Q, pred: F, then: T) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> bool,
+        T: FnOnce(&V) -> R,
+    {
+        let guard = pin();
+        let hash = self.inner.hash(key);
+
+        let old = self
+            .inner
+            .operate(&guard, |table, r| table.remove(r, hash, key, pred))?;
+        Some(then(unsafe { &*old }))
     }
 }
 
-impl<K, V> fmt::Debug for Map<K, V>
+impl<K, V, S> ConcurrentMap<K, V> for Map<K, V, S>
 where
-    K: fmt::Debug,
-    V: fmt::Debug,
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+    S: BuildHasher,
+{
+    type Iter<'a>
+        = IterCloned<'a, K, V>
+    where
+        Self: 'a;
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        Map::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.get_cloned(key)
+    }
+
+    fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        Map::get_or_insert_with(self, key, make)
+    }
+
+    fn update<F>(&mut self, key: &K, f: F) -> Option<V>
+    where
+        F: FnMut(&V) -> V,
+    {
+        Map::update(self, key, f)
+    }
+
+    fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        Map::compute(self, key, f)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_and(key, |_| true, V::clone)
+    }
+
+    fn len(&self) -> usize {
+        Map::len(self)
+    }
+
+    fn iter(&mut self) -> Self::Iter<'_> {
+        self.iter_cloned()
+    }
+}
+
+impl<K, V, S> fmt::Debug for Map<K, V, S>
+where
+    K: fmt::Debug + Send + 'static,
+    V: fmt::Debug + Send + 'static,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // TODO: use https://doc.rust-lang.org/std/fmt/struct.DebugMap.html
-        let mut all = String::new();
-        for i in 0..self.bsize {
-            // TODO: I'm _sure_ there's a better way to do this
-            all.push_str(&format!("{:?}", &self.mp[i]));
-        }
-        let ret: String = all.chars().skip(0).take(all.len() - 2).collect();
-        write!(f, "[{}]", ret)
+        let guard = pin();
+        f.debug_map().entries(self.iter(&guard)).finish()
     }
 }
 
@@ -381,108 +1333,576 @@ mod benchmarks {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use rand::{thread_rng, Rng};
-//     use std::thread;
-
-//     /*
-//     the data produced is a bit strange because of the way I take mod to test only even values
-//     are inserted so the end number of values should be n/2 (computer style) and the capacity
-//     of the map should be equal to the greatest power of 2 less than n/2.
-//     */
-//     #[test]
-//     fn hashmap_concurr() {
-//         let handle = Map::with_capacity(8); //changed this,
-//         let mut threads = vec![];
-//         let nthreads = 5;
-//         // let handle = MapHandle::new(Arc::clone(&new_hashmap).table.read().unwrap());
-//         for _ in 0..nthreads {
-//             let mut new_handle = handle.clone();
-
-//             threads.push(thread::spawn(move || {
-//                 let num_iterations = 1000000;
-//                 for _ in 0..num_iterations {
-//                     let mut rng = thread_rng();
-//                     let val = rng.gen_range(0, 128);
-//                     let two = rng.gen_range(0, 3);
-
-//                     if two % 3 == 0 {
-//                         new_handle.insert(val, val);
-//                     } else if two % 3 == 1 {
-//                         let v = new_handle.get(&val);
-//                         if v.is_some() {
-//                             assert_eq!(v.unwrap(), val);
-//                         }
-//                     } else {
-//                         new_handle.remove(&val);
-//                     }
-//                 }
-//             }));
-//         }
-//         for t in threads {
-//             t.join().unwrap();
-//         }
-//     }
-
-//     #[test]
-//     fn hashmap_delete() {
-//         let handle = Map::with_capacity(8);
-//         handle.insert(1, 3);
-//         handle.insert(2, 5);
-//         handle.insert(3, 8);
-//         handle.insert(4, 3);
-//         handle.insert(5, 4);
-//         handle.insert(6, 5);
-//         handle.insert(7, 3);
-//         handle.insert(8, 3);
-//         handle.insert(9, 3);
-//         handle.insert(10, 3);
-//         handle.insert(11, 3);
-//         handle.insert(12, 3);
-//         handle.insert(13, 3);
-//         handle.insert(14, 3);
-//         handle.insert(15, 3);
-//         handle.insert(16, 3);
-//         assert_eq!(handle.get(&1).unwrap(), 3);
-//         assert_eq!(handle.remove(&1), true);
-//         assert_eq!(handle.get(&1), None);
-//         assert_eq!(handle.remove(&2), true);
-//         assert_eq!(handle.remove(&16), true);
-//         assert_eq!(handle.get(&16), None);
-//     }
-
-//     #[test]
-//     fn hashmap_basics() {
-//         let new_hashmap = Map::with_capacity(8); //init with 2 buckets
-//                                                      //input values
-//         new_hashmap.insert(1, 1);
-//         new_hashmap.insert(2, 5);
-//         new_hashmap.insert(12, 5);
-//         new_hashmap.insert(13, 7);
-//         new_hashmap.insert(0, 0);
-
-//         new_hashmap.insert(20, 3);
-//         new_hashmap.insert(3, 2);
-//         new_hashmap.insert(4, 1);
-
-//         assert_eq!(new_hashmap.insert(20, 5).unwrap(), 3); //repeated new
-//         assert_eq!(new_hashmap.insert(3, 8).unwrap(), 2); //repeated new
-
-//         new_hashmap.insert(3, 8); //repeated
-
-//         assert_eq!(new_hashmap.get(&20).unwrap(), 5);
-//         assert_eq!(new_hashmap.get(&12).unwrap(), 5);
-//         assert_eq!(new_hashmap.get(&1).unwrap(), 1);
-//         assert_eq!(new_hashmap.get(&0).unwrap(), 0);
-//         assert!(new_hashmap.get(&3).unwrap() != 2); // test that it changed
-
-//         // try the same assert_eqs
-//         assert_eq!(new_hashmap.get(&20).unwrap(), 5);
-//         assert_eq!(new_hashmap.get(&12).unwrap(), 5);
-//         assert_eq!(new_hashmap.get(&1).unwrap(), 1);
-//         assert_eq!(new_hashmap.get(&0).unwrap(), 0);
-//         assert!(new_hashmap.get(&3).unwrap() != 2); // test that it changed
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{BuildHasherDefault, Hasher};
+    use std::mem;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread;
+    use table::MAX_LOAD_FACTOR;
+    use test_util::{ConstantHasher, Tracked, Tracker};
+
+    /*
+    the data produced is a bit strange because of the way I take mod to test only even values
+    are inserted so the end number of values should be n/2 (computer style) and the capacity
+    of the map should be equal to the greatest power of 2 less than n/2.
+    */
+    #[test]
+    fn hashmap_concurr() {
+        let handle = Map::with_capacity(8); //changed this,
+        let mut threads = vec![];
+        let nthreads = 5;
+        for _ in 0..nthreads {
+            let new_handle = handle.clone();
+
+            threads.push(thread::spawn(move || {
+                let num_iterations = 1000000;
+                for _ in 0..num_iterations {
+                    let mut rng = thread_rng();
+                    let val = rng.gen_range(0, 128);
+                    let two = rng.gen_range(0, 3);
+
+                    if two % 3 == 0 {
+                        new_handle.insert(val, val);
+                    } else if two % 3 == 1 {
+                        if let Some(v) = new_handle.get(&val) {
+                            assert_eq!(v, val);
+                        }
+                    } else {
+                        new_handle.remove(&val);
+                    }
+                }
+            }));
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn hashmap_delete() {
+        let handle = Map::with_capacity(8);
+        handle.insert(1, 3);
+        handle.insert(2, 5);
+        handle.insert(3, 8);
+        handle.insert(4, 3);
+        handle.insert(5, 4);
+        handle.insert(6, 5);
+        handle.insert(7, 3);
+        handle.insert(8, 3);
+        handle.insert(9, 3);
+        handle.insert(10, 3);
+        handle.insert(11, 3);
+        handle.insert(12, 3);
+        handle.insert(13, 3);
+        handle.insert(14, 3);
+        handle.insert(15, 3);
+        handle.insert(16, 3);
+        assert_eq!(handle.get(&1).unwrap(), 3);
+        assert!(handle.remove(&1));
+        assert_eq!(handle.get(&1), None);
+        assert!(handle.remove(&2));
+        assert!(handle.remove(&16));
+        assert_eq!(handle.get(&16), None);
+        assert!(!handle.remove(&16));
+        assert_eq!(handle.len(), 13);
+    }
+
+    #[test]
+    fn hashmap_basics() {
+        let new_hashmap = Map::with_capacity(8); //init with 2 buckets
+                                                 //input values
+        new_hashmap.insert(1, 1);
+        new_hashmap.insert(2, 5);
+        new_hashmap.insert(12, 5);
+        new_hashmap.insert(13, 7);
+        new_hashmap.insert(0, 0);
+
+        new_hashmap.insert(20, 3);
+        new_hashmap.insert(3, 2);
+        new_hashmap.insert(4, 1);
+
+        assert_eq!(new_hashmap.insert(20, 5).unwrap(), 3); //repeated new
+        assert_eq!(new_hashmap.insert(3, 8).unwrap(), 2); //repeated new
+
+        new_hashmap.insert(3, 8); //repeated
+
+        assert_eq!(new_hashmap.get(&20).unwrap(), 5);
+        assert_eq!(new_hashmap.get(&12).unwrap(), 5);
+        assert_eq!(new_hashmap.get(&1).unwrap(), 1);
+        assert_eq!(new_hashmap.get(&0).unwrap(), 0);
+        assert!(new_hashmap.get(&3).unwrap() != 2); // test that it changed
+
+        // try the same assert_eqs
+        assert_eq!(new_hashmap.get(&20).unwrap(), 5);
+        assert_eq!(new_hashmap.get(&12).unwrap(), 5);
+        assert_eq!(new_hashmap.get(&1).unwrap(), 1);
+        assert_eq!(new_hashmap.get(&0).unwrap(), 0);
+        assert!(new_hashmap.get(&3).unwrap() != 2); // test that it changed
+    }
+
+    #[test]
+    fn hashmap_remove_race() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        for i in 0..1000 {
+            handle.insert(i, i);
+        }
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let new_handle = handle.clone();
+                thread::spawn(move || (0..1000).filter(|i| new_handle.remove(i)).count())
+            })
+            .collect();
+
+        // every key was removed by exactly one thread, and is gone for good
+        let removed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(removed, 1000);
+        assert!(handle.is_empty());
+        assert_eq!(handle.iter(&pin()).count(), 0);
+    }
+
+    #[test]
+    fn hashmap_non_copy() {
+        let handle = Map::with_capacity(8);
+        assert_eq!(handle.insert(1, String::from("a")), None);
+        assert_eq!(handle.insert(1, String::from("b")), Some(String::from("a")));
+        assert_eq!(handle.get_cloned(&1), Some(String::from("b")));
+        assert_eq!(handle.get_and(&1, |v| v.len()), Some(1));
+        assert!(handle.remove(&1));
+        assert_eq!(handle.get_and(&1, |v| v.len()), None);
+    }
+
+    #[test]
+    fn hashmap_get_ref_batch() {
+        let handle = Map::with_capacity(8);
+        for i in 0..100 {
+            handle.insert(i, i.to_string());
+        }
+
+        let guard = pin();
+        let refs: Vec<_> = (0..100).map(|i| handle.get_ref(&i, &guard)).collect();
+        for i in 0..100 {
+            handle.remove(&i);
+        }
+        for (i, r) in refs.into_iter().enumerate() {
+            assert_eq!(r, Some(&i.to_string()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the guard must come from")]
+    fn hashmap_get_ref_foreign_guard() {
+        let handle = Map::with_capacity(8);
+        handle.insert(1, 42);
+
+        // garbage is only deferred to the default collector, so this guard protects nothing
+        let collector = epoch::Collector::new();
+        let local = collector.register();
+        let guard = local.pin();
+        handle.get_ref(&1, &guard);
+    }
+
+    #[test]
+    fn hashmap_iter() {
+        let handle = Map::with_capacity(2);
+        for i in 0..100 {
+            handle.insert(i, i * 2);
+        }
+        for i in (0..100).filter(|i| i % 3 == 0) {
+            handle.remove(&i);
+        }
+
+        let guard = pin();
+        let mut entries: Vec<_> = handle.iter(&guard).map(|(&k, &v)| (k, v)).collect();
+        entries.sort();
+        let expected: Vec<_> = (0..100)
+            .filter(|i| i % 3 != 0)
+            .map(|i| (i, i * 2))
+            .collect();
+        assert_eq!(entries, expected);
+
+        assert_eq!(handle.keys(&guard).count(), handle.len());
+        assert_eq!(
+            handle.values(&guard).sum::<i32>(),
+            expected.iter().map(|e| e.1).sum()
+        );
+    }
+
+    #[test]
+    fn hashmap_iter_foreign_guard() {
+        let handle = Map::with_capacity(8);
+        handle.insert(1, 42);
+
+        let collector = epoch::Collector::new();
+        let local = collector.register();
+        let guard = local.pin();
+        let iter = panic::catch_unwind(AssertUnwindSafe(|| handle.iter(&guard).count()));
+        assert!(iter.is_err());
+        let keys = panic::catch_unwind(AssertUnwindSafe(|| handle.keys(&guard).count()));
+        assert!(keys.is_err());
+        let values = panic::catch_unwind(AssertUnwindSafe(|| handle.values(&guard).count()));
+        assert!(values.is_err());
+    }
+
+    #[test]
+    fn hashmap_iter_concurr() {
+        // keys that stay in the map must be seen exactly once, even while other keys come and go
+        let handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            handle.insert(i, i);
+        }
+
+        let writer = handle.clone();
+        let t = thread::spawn(move || {
+            for i in 1000..20000 {
+                writer.insert(i, i);
+                if i % 2 == 0 {
+                    writer.remove(&i);
+                }
+            }
+        });
+
+        for _ in 0..10 {
+            let guard = pin();
+            let mut keys: Vec<_> = handle.keys(&guard).cloned().filter(|&k| k < 1000).collect();
+            keys.sort();
+            assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn hashmap_insert_race() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let new_handle = handle.clone();
+                thread::spawn(move || {
+                    let mut won = 0;
+                    let mut seen = Vec::new();
+                    for i in 0..1000 {
+                        if new_handle.try_insert(i, t).is_ok() {
+                            won += 1;
+                        }
+                        seen.push(new_handle.get_or_insert_with(i + 1000, || t));
+                    }
+                    (won, seen)
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        // every key was inserted by exactly one thread, and everyone saw the winner's value
+        assert_eq!(results.iter().map(|r| r.0).sum::<usize>(), 1000);
+        for (i, winner) in (0..1000).map(|i| (i, handle.get(&(i + 1000)).unwrap())) {
+            for r in &results {
+                assert_eq!(r.1[i], winner);
+            }
+        }
+        assert_eq!(handle.len(), 2000);
+    }
+
+    #[test]
+    fn hashmap_custom_hasher() {
+        let handle =
+            Map::with_capacity_and_hasher(4, BuildHasherDefault::<ConstantHasher>::default());
+        for i in 0..100 {
+            assert_eq!(handle.insert(i, i), None);
+        }
+        for i in (0..100).step_by(2) {
+            assert!(handle.remove(&i));
+        }
+        for i in 0..100 {
+            assert_eq!(handle.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+        assert_eq!(handle.len(), 50);
+    }
+
+    #[test]
+    fn hashmap_load_report() {
+        // every key collides, so they all end up in the same bucket
+        let handle =
+            Map::with_capacity_and_hasher(4, BuildHasherDefault::<ConstantHasher>::default());
+        for i in 0..100 {
+            handle.insert(i, i);
+        }
+        for i in (0..100).step_by(2) {
+            handle.remove(&i);
+        }
+
+        let report = handle.load_report();
+        assert_eq!(report.buckets, handle.inner.table.nbuckets());
+        assert_eq!(report.longest_chain, 50);
+        assert_eq!(report.histogram.len(), 51);
+        assert_eq!(report.histogram[50], 1);
+        assert_eq!(report.empty_buckets, report.buckets - 1);
+        assert_eq!(report.histogram.iter().sum::<usize>(), report.buckets);
+        assert_eq!(report.deleted_nodes, 0);
+    }
+
+    #[test]
+    fn hashmap_collision_resistant() {
+        // keys that all land in the same bucket under an unseeded hasher
+        let nbuckets = 256;
+        let keys: Vec<u64> = (0..)
+            .filter(|k| {
+                let mut hasher = DefaultHasher::new();
+                k.hash(&mut hasher);
+                hasher.finish() as usize & (nbuckets - 1) == 0
+            })
+            .take(nbuckets)
+            .collect();
+
+        let handle = Map::with_capacity(nbuckets);
+        for &k in &keys {
+            handle.insert(k, k);
+        }
+        for &k in &keys {
+            assert_eq!(handle.get(&k), Some(k));
+        }
+
+        // the map's own seed spreads them out again
+        let inner = &handle.inner;
+        assert_eq!(inner.table.nbuckets(), nbuckets);
+        let mut load = vec![0; nbuckets];
+        for k in &keys {
+            load[inner.hash(k) & (nbuckets - 1)] += 1;
+        }
+        assert!(*load.iter().max().unwrap() <= 10, "{:?}", load);
+
+        // and every map is seeded differently
+        let other = Map::<u64, u64>::with_capacity(nbuckets);
+        assert!(keys.iter().any(|k| inner.hash(k) != other.inner.hash(k)));
+    }
+
+    #[test]
+    fn hashmap_borrowed_keys() {
+        let handle = Map::with_capacity(4);
+        for i in 0..100 {
+            handle.insert(i.to_string(), i);
+        }
+
+        // look up `String` keys by `&str`, without allocating
+        for i in 0..100 {
+            let key = i.to_string();
+            assert!(handle.contains_key(key.as_str()));
+            assert_eq!(handle.get(key.as_str()), Some(i));
+        }
+        assert!(!handle.contains_key("100"));
+        assert_eq!(handle.update("1", |v| v + 1), Some(2));
+        assert!(handle.remove("1"));
+        assert!(!handle.contains_key("1"));
+        assert_eq!(handle.len(), 99);
+    }
+
+    #[test]
+    fn hashmap_drop_frees_everything() {
+        let tracker = Arc::new(Tracker::default());
+        let handle = Map::with_capacity(2);
+        let nthreads = 4;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let new_handle = handle.clone();
+                let tracker = Arc::clone(&tracker);
+                thread::spawn(move || {
+                    let key = |i| (t * 200 + i, Tracked::new(&tracker));
+                    for i in 0..200 {
+                        new_handle.insert(key(i), Tracked::new(&tracker));
+                    }
+                    for i in 0..200 {
+                        match i % 4 {
+                            0 => drop(new_handle.remove(&key(i))),
+                            1 => drop(new_handle.insert(key(i), Tracked::new(&tracker))),
+                            2 => drop(new_handle.try_insert(key(i), Tracked::new(&tracker))),
+                            _ => drop(new_handle.compute(key(i), |v| v.cloned())),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(handle.len(), nthreads * 150);
+
+        // every key and value, whether still in the map or retired, is dropped exactly once
+        drop(handle);
+
+        // retired ones are only dropped once the epoch has advanced far enough
+        for _ in 0..10_000 {
+            if tracker.live() == 0 {
+                break;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(tracker.live(), 0);
+    }
+
+    #[test]
+    fn hashmap_update_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let nkeys = 64;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = i % nkeys;
+                        // the first increment of each key inserts it
+                        new_handle.compute(key, |v| Some(v.map_or(1, |v| v + 1)));
+                        new_handle.compute(key + nkeys, |v| Some(v.map_or(1, |v| v + 1)));
+                        new_handle.update(&(key + nkeys), |v| v + 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // no increment was lost
+        let total: usize = (0..nkeys).map(|i| handle.get(&i).unwrap()).sum();
+        assert_eq!(total, nthreads * 1000);
+        let total: usize = (nkeys..2 * nkeys).map(|i| handle.get(&i).unwrap()).sum();
+        assert_eq!(total, 2 * nthreads * 1000);
+        assert_eq!(handle.len(), 2 * nkeys);
+
+        // removing through compute is reflected in the length
+        for i in 0..nkeys {
+            assert_eq!(handle.compute(i, |_| None), None);
+        }
+        assert_eq!(handle.len(), nkeys);
+        assert_eq!(handle.update(&0, |v| v + 1), None);
+    }
+
+    #[test]
+    fn hashmap_compare_exchange_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        handle.insert(0, 0);
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        // increment a counter with a read-modify-CAS loop
+                        let mut cur = new_handle.get(&0).unwrap();
+                        while let Err(actual) = new_handle.compare_exchange(&0, &cur, cur + 1) {
+                            cur = actual.unwrap();
+                        }
+
+                        // take a lease, and release it only if we still hold it
+                        let key = 1 + i % 16;
+                        if new_handle.try_insert(key, t).is_ok() {
+                            assert!(new_handle.remove_if_eq(&key, &t));
+                        } else {
+                            assert!(!new_handle.remove_if_eq(&key, &nthreads));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(handle.get(&0), Some(nthreads * 1000));
+        assert_eq!(handle.len(), 1);
+        assert!(!handle.remove_if(&0, |v| *v == 0));
+        assert!(handle.remove_if(&0, |v| *v == nthreads * 1000));
+        assert!(handle.is_empty());
+    }
+
+    #[test]
+    fn hashmap_grows() {
+        let handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            assert_eq!(handle.insert(i, i), None);
+        }
+
+        let nbuckets = handle.inner.table.nbuckets();
+        assert!(nbuckets >= 1000 / MAX_LOAD_FACTOR);
+        assert_eq!(handle.len(), 1000);
+
+        for i in 0..1000 {
+            assert_eq!(handle.get(&i), Some(i));
+        }
+        for i in (0..1000).filter(|i| i % 2 == 0) {
+            assert!(handle.remove(&i));
+        }
+        for i in 0..1000 {
+            assert_eq!(handle.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+    }
+
+    #[test]
+    fn hashmap_grows_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let per_thread = 10000;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in (t * per_thread)..((t + 1) * per_thread) {
+                        assert_eq!(new_handle.insert(i, i), None);
+                        assert_eq!(new_handle.get(&i), Some(i));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(handle.len(), nthreads * per_thread);
+        for i in 0..(nthreads * per_thread) {
+            assert_eq!(handle.get(&i), Some(i));
+        }
+    }
+
+    #[test]
+    fn hashmap_stats() {
+        let handle: Map<usize, usize> = Map::with_capacity(16);
+        let other = handle.clone();
+        for i in 0..10 {
+            handle.insert(i, i);
+        }
+        for i in 0..5 {
+            handle.remove(&i);
+        }
+
+        let stats = handle.stats();
+        assert!(stats.retired_nodes <= 5 && stats.retired_values <= 5);
+        assert_eq!(stats.handles, 2);
+        let entry = mem::size_of::<Node<usize, usize>>() + mem::size_of::<usize>();
+        assert_eq!(stats.live_bytes, 5 * entry);
+
+        // the epoch collector gets to the garbage once no thread can be reading it anymore
+        let retired = |h: &Map<usize, usize>| h.stats().retired_nodes + h.stats().retired_values;
+        for _ in 0..10_000 {
+            if retired(&handle) == 0 {
+                break;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(retired(&handle), 0);
+
+        drop(other);
+        assert_eq!(handle.stats().handles, 1);
+    }
+}
@@ -0,0 +1,1428 @@
+//! A concurrent hash map implementation with hazard-pointer-based memory management.
+//!
+//! This implementation uses the same lock-free [split-ordered
+//! list](https://dl.acm.org/citation.cfm?id=1147958) as [`manual`](::manual), but reclaims memory
+//! with [hazard pointers](https://dl.acm.org/citation.cfm?id=987595) instead of epochs. Before a
+//! handle dereferences a node or value, it publishes the pointer in one of a handful of hazard
+//! pointers, and re-checks that the pointer is still reachable from the map. When a handle has
+//! retired enough garbage, it frees everything that no handle has published.
+//!
+//! Since a handle only ever protects a few pointers at a time, the amount of garbage that cannot
+//! be freed is bounded by the number of handles, no matter what the other threads are doing. A
+//! thread that stalls in the middle of an operation holds on to at most a handful of nodes and
+//! values, where with epoch-based schemes it keeps everything retired since from being freed. The
+//! price is that every step through the list has to publish and re-check a hazard pointer, which
+//! makes reads somewhat more expensive.
+//!
+//! The interface to this map is the same as that of [`manual::Map`](::manual::Map): creating a
+//! [`Map`] gives you a [`MapHandle`], which you clone to access the map from other threads. Values
+//! are read by copying or cloning them out of the map, or through a closure that is given
+//! temporary access to them.
+
+use reclaim::{self, Reclaimer, SLOTS};
+use std::borrow::Borrow;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use table::{self, Buckets, Cursor as TableCursor, Node, Table};
+use {ConcurrentMap, LoadReport, Stats};
+
+mod pointers;
+use self::pointers::{protected, Hazards, Registry, Retired};
+
+const OSC: Ordering = Ordering::SeqCst;
+/// The least amount of garbage a handle retires between two scans.
+const SCAN_THRESHOLD: usize = 64;
+
+/// Everything a handle has unlinked from the map.
+type Garbage<K, V> = Retired<Node<K, V>, V, Buckets<K, V>>;
+
+/// The parts of a handle that an operation on the table needs: the hazard pointers to protect
+/// what it reads with, and the garbage to retire what it unlinks into.
+struct Local<'a, K: 'a, V: 'a> {
+    hazards: &'a Hazards,
+    retired: &'a mut Garbage<K, V>,
+}
+
+impl<'a, K, V> Reclaimer<K, V> for Local<'a, K, V> {
+    fn enter(&mut self) {}
+
+    fn exit(&mut self) {
+        self.hazards.clear();
+    }
+
+    fn protect<T>(&mut self, slot: usize, ptr: *mut T) {
+        self.hazards.protect(slot, ptr);
+    }
+
+    fn retire_node(&mut self, node: *mut Node<K, V>) {
+        self.retired.nodes.push(node);
+    }
+
+    fn retire_value(&mut self, value: *mut V) {
+        self.retired.vals.push(value);
+    }
+
+    fn retire_buckets(&mut self, buckets: *mut Buckets<K, V>) {
+        self.retired.buckets.push(buckets);
+    }
+}
+
+/// A handle to a shared [`Map`].
+///
+/// Any operation performed on this handle affects the map seen by all other related `MapHandle`
+/// instances. To get another handle to the `Map`, simply clone any of its handles.
+pub struct MapHandle<K, V, S = RandomState> {
+    map: Arc<Map<K, V, S>>,
+    hazards: Arc<Hazards>,
+    retired: Garbage<K, V>,
+    /// Scan for garbage to free once this much has been retired.
+    scan_at: usize,
+}
+
+unsafe impl<K, V, S> Send for MapHandle<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send + Sync,
+{
+}
+
+impl<K, V, S> MapHandle<K, V, S> {
+    /// Creates a new handle to `map`, and registers its hazard pointers.
+    fn register(map: Arc<Map<K, V, S>>) -> Self {
+        let ret = MapHandle {
+            map,
+            hazards: Arc::new(Hazards::default()),
+            retired: Garbage::default(),
+            scan_at: SCAN_THRESHOLD,
+        };
+
+        let mut handles_vec = ret.map.handles.write().unwrap();
+        handles_vec.push(Arc::clone(&ret.hazards));
+        drop(handles_vec);
+
+        ret
+    }
+
+    fn local(&mut self) -> Local<'_, K, V> {
+        Local {
+            hazards: &self.hazards,
+            retired: &mut self.retired,
+        }
+    }
+
+    /// Runs a single operation `f` on the map's table.
+    fn operate<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&Table<K, V>, &mut Local<'_, K, V>) -> R,
+    {
+        let mut hp = Local {
+            hazards: &self.hazards,
+            retired: &mut self.retired,
+        };
+        let table = &self.map.table;
+        let ret = reclaim::critical(&mut hp, |hp| f(table, hp));
+        self.scan_if_due();
+
+        ret
+    }
+
+    /// Ends the critical section of a [`Cursor`]: drops the protection of everything it looked at,
+    /// and frees garbage if enough has been retired since the last scan.
+    fn release(&mut self) {
+        self.local().exit();
+        self.scan_if_due();
+    }
+
+    /// Frees garbage if enough has been retired since the last scan.
+    fn scan_if_due(&mut self) {
+        if self.retired.len() >= self.scan_at {
+            self.scan();
+        }
+    }
+
+    /// Frees all of the garbage retired by this handle, and by dropped handles, that no handle
+    /// is protecting.
+    fn scan(&mut self) {
+        self.map.scans.fetch_add(1, OSC);
+
+        // garbage has to be retired before the hazard pointers are read, or a handle could
+        // protect it after it has been checked. so take over any orphans first.
+        let orphans = self.map.orphans.try_lock();
+        let protected = protected(&self.map.handles);
+
+        self.retired.scan(&protected);
+        if let Ok(mut orphans) = orphans {
+            orphans.scan(&protected);
+        }
+
+        // every handle protects at most `SLOTS` pointers, so at most that many retired pointers
+        // per handle can survive a scan. waiting for twice as much new garbage before the next
+        // scan means each scan frees at least half of what it looks at, while keeping the garbage
+        // this handle holds on to bounded by the number of handles.
+        let nhandles = self.map.handles.read().unwrap().len();
+        self.scan_at = self.retired.len() + cmp::max(SCAN_THRESHOLD, 2 * SLOTS * nhandles);
+    }
+}
+
+impl<K, V, S> MapHandle<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map did not have this key present, `None` is returned.
+    ///
+    /// If the map did have this key present, the value is updated, and a clone of the old value is
+    /// returned. The key is not updated, though; this matters for types that can be `==` without
+    /// being identical.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.insert(37, "a"), None);
+    /// assert_eq!(map.is_empty(), false);
+    ///
+    /// map.insert(37, "b");
+    /// assert_eq!(map.insert(37, "c"), Some("b"));
+    /// assert_eq!(map.get(&37), Some("c"));
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
+
+        self.operate(|table, hp| {
+            table
+                .insert(hp, hash, key, value)
+                .map(|v| unsafe { (*v).clone() })
+        })
+    }
+
+    /// Inserts a key-value pair into the map only if the key is not already present.
+    ///
+    /// Returns `Ok(())` if the value was inserted, and gives the value back as `Err(value)` if the
+    /// key was already present. Of several threads racing to insert the same key, exactly one
+    /// succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.try_insert(37, "a"), Ok(()));
+    /// assert_eq!(map.try_insert(37, "b"), Err("b"));
+    /// assert_eq!(map.get(&37), Some("a"));
+    /// ```
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), V> {
+        let mut value = Some(value);
+        let hash = self.map.hash(&key);
+
+        let ret =
+            self.operate(|table, hp| table.insert_new(hp, hash, key, || value.take().unwrap()));
+
+        match ret {
+            Ok(_) => Ok(()),
+            Err((_, unused)) => Err(unused.or(value).unwrap()),
+        }
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting `value` if the key
+    /// is not already present.
+    ///
+    /// Of several threads racing to insert the same key, exactly one succeeds, and all of them
+    /// return the value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert(37, "a"), "a");
+    /// assert_eq!(map.get_or_insert(37, "b"), "a");
+    /// ```
+    pub fn get_or_insert(&mut self, key: K, value: V) -> V
+    where
+        V: Clone,
+    {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting the value returned
+    /// by `make` if the key is not already present.
+    ///
+    /// `make` is only called if the key is not present, but if another thread inserts the same key
+    /// concurrently, the value it returned may be dropped without ever being inserted. Of several
+    /// threads racing to insert the same key, exactly one succeeds, and all of them return the
+    /// value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert_with(37, || String::from("a")), "a");
+    /// assert_eq!(map.get_or_insert_with(37, || unreachable!()), "a");
+    /// ```
+    pub fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
+
+        self.operate(|table, hp| match table.insert_new(hp, hash, key, make) {
+            Ok(v) | Err((v, _)) => unsafe { (*v).clone() },
+        })
+    }
+
+    /// Returns `true` if the map contains a value for the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(String::from("a"), 1);
+    /// assert!(map.contains_key("a"));
+    /// assert!(!map.contains_key("b"));
+    /// ```
+    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_and(key, |_| ()).is_some()
+    }
+
+    /// Returns a copy of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some("a"));
+    /// assert_eq!(map.get(&2), None);
+    /// ```
+    pub fn get<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Copy,
+    {
+        self.get_and(key, |v| *v)
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, String::from("a"));
+    /// assert_eq!(map.get_cloned(&1), Some(String::from("a")));
+    /// assert_eq!(map.get_cloned(&2), None);
+    /// ```
+    pub fn get_cloned<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.get_and(key, V::clone)
+    }
+
+    /// Applies a function to the value corresponding to the key, and returns the result.
+    ///
+    /// The value is protected by a hazard pointer while the function is accessing it. Unlike with
+    /// the epoch-based maps, a long-running function only keeps this one value from being freed.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, vec![1, 2, 3]);
+    /// assert_eq!(map.get_and(&1, |v| v.len()), Some(3));
+    /// assert_eq!(map.get_and(&2, |v| v.len()), None);
+    /// ```
+    pub fn get_and<Q, F, R>(&mut self, key: &Q, then: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, hp| table.get(hp, hash, key).map(|v| then(unsafe { &*v })))
+    }
+
+    /// Atomically replaces the value corresponding to the key with the result of applying `f` to
+    /// it, and returns a clone of the new value. Returns `None`, without calling `f`, if the key is
+    /// not present.
+    ///
+    /// If another thread changes the value after `f` has read it, `f` is called again with the new
+    /// value, so `f` may be called several times and should not have side effects.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.update(&1, |v| v + 1), Some(11));
+    /// assert_eq!(map.update(&2, |v| v + 1), None);
+    /// ```
+    pub fn update<Q, F>(&mut self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> V,
+        V: Clone,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, hp| {
+            table
+                .update(hp, hash, key, |v| Some(f(v)))
+                .ok()
+                .map(|new| unsafe { (*new).clone() })
+        })
+    }
+
+    /// Atomically replaces the value corresponding to the key with `new`, but only if the current
+    /// value is equal to `expected`.
+    ///
+    /// Returns `Ok(())` if the value was replaced. Otherwise, returns a clone of the current value,
+    /// or `None` if the key is not present.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.compare_exchange(&1, &"a", "b"), Ok(()));
+    /// assert_eq!(map.compare_exchange(&1, &"a", "c"), Err(Some("b")));
+    /// assert_eq!(map.compare_exchange(&2, &"a", "c"), Err(None));
+    /// ```
+    pub fn compare_exchange<Q>(&mut self, key: &Q, expected: &V, new: V) -> Result<(), Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, hp| {
+            let ret = table.update(hp, hash, key, |v| {
+                if v == expected {
+                    Some(new.clone())
+                } else {
+                    None
+                }
+            });
+            match ret {
+                Ok(_) => Ok(()),
+                Err(current) => Err(current.map(|v| unsafe { (*v).clone() })),
+            }
+        })
+    }
+
+    /// Atomically sets the value corresponding to the key to the result of applying `f` to the
+    /// current value, or to `None` if the key is not present, and returns a clone of the new
+    /// value.
+    ///
+    /// If `f` returns `None`, the key is removed from the map. If the key was not present and `f`
+    /// returns `Some`, the key is inserted. As with [`MapHandle::update`], `f` may be called several
+    /// times if other threads modify the key concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(0));
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(1));
+    /// assert_eq!(map.compute(1, |_| None), None);
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
+
+        self.operate(|table, hp| {
+            table
+                .compute(hp, hash, key, f)
+                .map(|v| unsafe { (*v).clone() })
+        })
+    }
+
+    /// Removes a key from the map, returning a clone of the value at the key if the key was
+    /// previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove(&1), Some("a"));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.remove_if(key, |_| true)
+    }
+
+    /// Removes a key from the map if `pred` returns `true` for its value, returning a clone of the
+    /// value if the key was removed.
+    ///
+    /// The check and the removal happen atomically: the key is only removed if its value has not
+    /// changed since `pred` was called. If the value did change, `pred` is called again with the
+    /// new value.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.remove_if(&1, |v| *v > 10), None);
+    /// assert_eq!(map.remove_if(&1, |v| *v == 10), Some(10));
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> bool,
+        V: Clone,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, hp| {
+            table
+                .remove(hp, hash, key, pred)
+                .map(|v| unsafe { (*v).clone() })
+        })
+    }
+
+    /// Removes a key from the map if its value is equal to `expected`, returning a clone of the
+    /// value if the key was removed.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove_if_eq(&1, &"b"), None);
+    /// assert_eq!(map.remove_if_eq(&1, &"a"), Some("a"));
+    /// ```
+    pub fn remove_if_eq<Q>(&mut self, key: &Q, expected: &V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        self.remove_if(key, |v| v == expected)
+    }
+
+    /// Returns the number of elements in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut a = Map::with_capacity(16);
+    /// assert_eq!(a.len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.map.table.len()
+    }
+
+    /// Returns true if the map contains no elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut a = Map::with_capacity(16);
+    /// assert!(a.is_empty());
+    /// a.insert(1, "a");
+    /// assert!(!a.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.map.table.len() == 0
+    }
+}
+
+impl<K, V, S> MapHandle<K, V, S> {
+    /// An iterator visiting all key-value pairs in the map, yielding clones of each key and
+    /// value.
+    ///
+    /// The iterator is weakly consistent: every key that is in the map for the entire iteration is
+    /// yielded exactly once, while keys that are inserted or removed concurrently may or may not
+    /// be yielded. Keys are yielded in no particular order.
+    ///
+    /// The iterator only protects the entry it is at, so unlike with the epoch-based maps, holding
+    /// on to it does not keep other handles from freeing memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut entries: Vec<_> = map.iter().collect();
+    /// entries.sort();
+    /// assert_eq!(entries, vec![(1, "a"), (2, "b")]);
+    /// ```
+    pub fn iter(&mut self) -> Iter<'_, K, V, S> {
+        Iter {
+            cursor: Cursor::new(self),
+        }
+    }
+
+    /// An iterator visiting all keys in the map, yielding clones of each key.
+    ///
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut keys: Vec<_> = map.keys().collect();
+    /// keys.sort();
+    /// assert_eq!(keys, vec![1, 2]);
+    /// ```
+    pub fn keys(&mut self) -> Keys<'_, K, V, S> {
+        Keys {
+            cursor: Cursor::new(self),
+        }
+    }
+
+    /// An iterator visiting all values in the map, yielding clones of each value.
+    ///
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut values: Vec<_> = map.values().collect();
+    /// values.sort();
+    /// assert_eq!(values, vec!["a", "b"]);
+    /// ```
+    pub fn values(&mut self) -> Values<'_, K, V, S> {
+        Values {
+            cursor: Cursor::new(self),
+        }
+    }
+
+    /// Returns statistics about the memory this handle and the map are holding on to.
+    ///
+    /// The retired nodes and values are those retired by this handle that have not been freed
+    /// yet. `cleanups` counts the scans of all handles for garbage to free. Scans never wait for
+    /// other threads, so `wait_time` is always zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.remove(&1);
+    ///
+    /// let stats = map.stats();
+    /// assert_eq!(stats.retired_nodes, 1);
+    /// assert_eq!(stats.retired_values, 1);
+    /// assert_eq!(stats.handles, 1);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        Stats {
+            retired_nodes: self.retired.nodes.len(),
+            retired_values: self.retired.vals.len(),
+            handles: self.map.handles.read().unwrap().len(),
+            cleanups: self.map.scans.load(OSC),
+            wait_time: Duration::from_secs(0),
+            live_bytes: self.map.table.live_bytes(),
+        }
+    }
+
+    /// Walks the whole map, and reports how its keys are spread over its buckets.
+    ///
+    /// This visits every node in the map, but only ever protects a few of them at a time. The
+    /// report is not a consistent snapshot: keys that are inserted or removed during the walk may
+    /// or may not be counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// for i in 0..10 {
+    ///     map.insert(i, i);
+    /// }
+    ///
+    /// let report = map.load_report();
+    /// assert_eq!(report.buckets, 16);
+    /// assert_eq!(report.histogram.iter().sum::<usize>(), 16);
+    /// assert_eq!(report.deleted_nodes, 0);
+    /// ```
+    pub fn load_report(&mut self) -> LoadReport {
+        self.operate(|table, hp| table.load_report(hp))
+    }
+}
+
+/// Walks the map's table, keeping the node it is at protected in the
+/// [`ANCHOR`](::reclaim::ANCHOR) hazard pointer.
+struct Cursor<'a, K: 'a, V: 'a, S: 'a> {
+    handle: &'a mut MapHandle<K, V, S>,
+    cursor: TableCursor<K, V>,
+}
+
+impl<'a, K, V, S> Cursor<'a, K, V, S> {
+    fn new(handle: &'a mut MapHandle<K, V, S>) -> Self {
+        handle.local().enter();
+        let cursor = TableCursor::new(&handle.map.table);
+        Cursor { handle, cursor }
+    }
+
+    /// Advances to the next entry, and calls `then` with its key and value.
+    fn next_and<F, R>(&mut self, then: F) -> Option<R>
+    where
+        F: FnOnce(&K, &V) -> R,
+    {
+        let handle = &mut *self.handle;
+        let mut hp = Local {
+            hazards: &handle.hazards,
+            retired: &mut handle.retired,
+        };
+        // the hazard pointers are only cleared when the cursor is dropped
+        let (k, v) = unsafe { self.cursor.next(&handle.map.table, &mut hp)? };
+        Some(then(k, v))
+    }
+}
+
+impl<'a, K, V, S> Drop for Cursor<'a, K, V, S> {
+    fn drop(&mut self) {
+        self.handle.release();
+    }
+}
+
+/// An iterator over the entries of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::iter`]. See its documentation for more.
+pub struct Iter<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|k, v| (k.clone(), v.clone()))
+    }
+}
+
+/// An iterator over the keys of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::keys`]. See its documentation for more.
+pub struct Keys<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Keys<'a, K, V, S>
+where
+    K: Clone,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|k, _| k.clone())
+    }
+}
+
+/// An iterator over the values of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::values`]. See its documentation for more.
+pub struct Values<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Values<'a, K, V, S>
+where
+    V: Clone,
+{
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|_, v| v.clone())
+    }
+}
+
+impl<K, V, S> ConcurrentMap<K, V> for MapHandle<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    type Iter<'a>
+        = Iter<'a, K, V, S>
+    where
+        Self: 'a;
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        MapHandle::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.get_cloned(key)
+    }
+
+    fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        MapHandle::get_or_insert_with(self, key, make)
+    }
+
+    fn update<F>(&mut self, key: &K, f: F) -> Option<V>
+    where
+        F: FnMut(&V) -> V,
+    {
+        MapHandle::update(self, key, f)
+    }
+
+    fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        MapHandle::compute(self, key, f)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        MapHandle::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        MapHandle::len(self)
+    }
+
+    fn iter(&mut self) -> Self::Iter<'_> {
+        MapHandle::iter(self)
+    }
+}
+
+impl<K, V, S> Clone for MapHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        Self::register(Arc::clone(&self.map))
+    }
+}
+
+impl<K, V, S> Drop for MapHandle<K, V, S> {
+    fn drop(&mut self) {
+        // deregister first, so that our own hazard pointers do not keep anything alive, even if
+        // we are unwinding out of an operation
+        let mut handles_vec = self.map.handles.write().unwrap();
+        handles_vec.retain(|h| !Arc::ptr_eq(h, &self.hazards));
+        drop(handles_vec);
+
+        // free what we can, and leave the rest to the handles that remain
+        let protected = protected(&self.map.handles);
+        self.retired.scan(&protected);
+        if self.retired.len() != 0 {
+            let mut orphans = self.map.orphans.lock().unwrap();
+            orphans.append(&mut self.retired);
+        }
+    }
+}
+
+/// A shared, concurrent hash map.
+///
+/// See [`MapHandle`] for how to interact with this map.
+pub struct Map<K, V, S = RandomState> {
+    table: Table<K, V>,
+    hash_builder: S,
+    handles: Registry,
+    /// Garbage of dropped handles that was still protected when they were dropped.
+    orphans: Mutex<Garbage<K, V>>,
+    /// The number of times the handles have scanned for garbage to free.
+    scans: AtomicUsize,
+}
+
+impl<K, V> Map<K, V, RandomState> {
+    /// Create a new, shared map and return a handle to it.
+    ///
+    /// The map will initially have `nbuckets` buckets (rounded up to a power of two) to distribute
+    /// stored keys among. The number of buckets doubles whenever the map holds more than two keys
+    /// per bucket on average, so `nbuckets` only needs to be a rough estimate.
+    ///
+    /// Keys are hashed with a [`RandomState`] that is seeded differently for every map, which
+    /// makes it hard for an attacker to pick keys that all end up in the same bucket.
+    pub fn with_capacity(nbuckets: usize) -> MapHandle<K, V> {
+        Self::with_capacity_and_hasher(nbuckets, RandomState::new())
+    }
+}
+
+impl<K, V, S> Map<K, V, S> {
+    /// Create a new, shared map that uses `hash_builder` to hash keys, and return a handle to it.
+    ///
+    /// See [`Map::with_capacity`] for the meaning of `nbuckets`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::hazard::Map;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    ///
+    /// let hasher = BuildHasherDefault::<DefaultHasher>::default();
+    /// let mut map = Map::with_capacity_and_hasher(16, hasher);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some("a"));
+    /// ```
+    pub fn with_capacity_and_hasher(nbuckets: usize, hash_builder: S) -> MapHandle<K, V, S> {
+        let new_hashmap = Map {
+            table: Table::new(nbuckets),
+            hash_builder,
+            handles: RwLock::new(Vec::new()),
+            orphans: Mutex::new(Garbage::default()),
+            scans: AtomicUsize::new(0),
+        };
+        MapHandle::register(Arc::new(new_hashmap))
+    }
+}
+
+impl<K, V, S> Map<K, V, S>
+where
+    S: BuildHasher,
+{
+    fn hash<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Hash,
+    {
+        table::hash(&self.hash_builder, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+    use std::hash::Hasher;
+    use std::mem;
+    use std::sync::mpsc;
+    use std::thread;
+    use table::MAX_LOAD_FACTOR;
+    use test_util::{Tracked, Tracker};
+
+    #[test]
+    fn hazard_concurr() {
+        let handle = Map::with_capacity(8);
+        let nthreads = 10;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    let mut rng = thread_rng();
+                    for _ in 0..200000 {
+                        let val = rng.gen_range(0, 8);
+                        match rng.gen_range(0, 3) {
+                            0 => {
+                                new_handle.insert(val, val);
+                            }
+                            1 => {
+                                if let Some(v) = new_handle.get(&val) {
+                                    assert_eq!(v, val);
+                                }
+                            }
+                            _ => {
+                                new_handle.remove(&val);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn hazard_basics() {
+        let mut handle = Map::with_capacity(8);
+        for i in 0..16 {
+            assert_eq!(handle.insert(i, i * 3), None);
+        }
+        assert_eq!(handle.insert(1, 5), Some(3));
+        assert_eq!(handle.get(&1), Some(5));
+        assert_eq!(handle.remove(&1), Some(5));
+        assert_eq!(handle.get(&1), None);
+        assert_eq!(handle.remove(&1), None);
+        assert_eq!(handle.get(&15), Some(45));
+        assert_eq!(handle.len(), 15);
+    }
+
+    #[test]
+    fn hazard_grows() {
+        let mut handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            assert_eq!(handle.insert(i, i), None);
+        }
+
+        let nbuckets = handle.map.table.nbuckets();
+        assert!(nbuckets >= 1000 / MAX_LOAD_FACTOR);
+        assert_eq!(handle.len(), 1000);
+
+        for i in (0..1000).filter(|i| i % 2 == 0) {
+            assert_eq!(handle.remove(&i), Some(i));
+        }
+        for i in 0..1000 {
+            assert_eq!(handle.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+    }
+
+    #[test]
+    fn hazard_grows_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let per_thread = 10000;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in (t * per_thread)..((t + 1) * per_thread) {
+                        assert_eq!(new_handle.insert(i, i), None);
+                        assert_eq!(new_handle.get(&i), Some(i));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let mut handle = handle;
+        assert_eq!(handle.len(), nthreads * per_thread);
+        for i in 0..(nthreads * per_thread) {
+            assert_eq!(handle.get(&i), Some(i));
+        }
+    }
+
+    #[test]
+    fn hazard_non_copy() {
+        let mut handle = Map::with_capacity(8);
+        assert_eq!(handle.insert(1, String::from("a")), None);
+        assert_eq!(handle.insert(1, String::from("b")), Some(String::from("a")));
+        assert_eq!(handle.get_cloned(&1), Some(String::from("b")));
+        assert_eq!(handle.get_and(&1, |v| v.len()), Some(1));
+        assert_eq!(handle.remove(&1), Some(String::from("b")));
+        assert_eq!(handle.get_and(&1, |v| v.len()), None);
+    }
+
+    #[test]
+    fn hazard_stalled_reader() {
+        let mut handle = Map::with_capacity(16);
+        handle.insert(0, Arc::new(0));
+
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let mut reader = handle.clone();
+        let t = thread::spawn(move || {
+            reader.get_and(&0, |_| {
+                entered_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            });
+        });
+        entered_rx.recv().unwrap();
+
+        // the reader only holds on to the value it is reading, so the garbage everyone else
+        // retires stays bounded no matter how long it takes
+        let tracker = Arc::new(0);
+        let nhandles = handle.map.handles.read().unwrap().len();
+        let bound = SCAN_THRESHOLD.max(2 * SLOTS * nhandles) + SLOTS * nhandles;
+        for i in 1..10000 {
+            handle.insert(i, Arc::clone(&tracker));
+            handle.insert(i, Arc::clone(&tracker));
+            handle.remove(&i);
+            assert!(handle.retired.len() <= bound);
+        }
+        handle.insert(0, Arc::new(1));
+        assert!(Arc::strong_count(&tracker) <= bound);
+
+        release_tx.send(()).unwrap();
+        t.join().unwrap();
+        drop(handle);
+        assert_eq!(Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn hazard_stats() {
+        let mut handle: MapHandle<usize, usize> = Map::with_capacity(16);
+        let other = handle.clone();
+        for i in 0..10 {
+            handle.insert(i, i);
+        }
+        for i in 0..5 {
+            handle.remove(&i);
+        }
+
+        let stats = handle.stats();
+        assert_eq!(stats.retired_nodes, 5);
+        assert_eq!(stats.retired_values, 5);
+        assert_eq!(stats.handles, 2);
+        assert_eq!(stats.cleanups, 0);
+        let entry = mem::size_of::<Node<usize, usize>>() + mem::size_of::<usize>();
+        assert_eq!(stats.live_bytes, 5 * entry);
+
+        handle.scan();
+        let stats = handle.stats();
+        assert_eq!((stats.retired_nodes, stats.retired_values), (0, 0));
+        assert_eq!(stats.cleanups, 1);
+
+        drop(other);
+        assert_eq!(handle.stats().handles, 1);
+    }
+
+    #[test]
+    fn hazard_load_report() {
+        let mut handle = Map::with_capacity(16);
+        for i in 0..100 {
+            handle.insert(i, i);
+        }
+        for i in (0..100).step_by(2) {
+            handle.remove(&i);
+        }
+
+        let report = handle.load_report();
+        assert_eq!(report.buckets, handle.map.table.nbuckets());
+        let keys: usize = report
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(n, b)| n * b)
+            .sum();
+        assert_eq!(keys, 50);
+        assert_eq!(report.histogram.iter().sum::<usize>(), report.buckets);
+        assert_eq!(report.histogram.len(), report.longest_chain + 1);
+        assert_eq!(report.empty_buckets, report.histogram[0]);
+        assert_eq!(report.deleted_nodes, 0);
+    }
+
+    #[test]
+    fn hazard_iter() {
+        let mut handle = Map::with_capacity(2);
+        for i in 0..100 {
+            handle.insert(i, i * 2);
+        }
+        for i in (0..100).filter(|i| i % 3 == 0) {
+            handle.remove(&i);
+        }
+
+        let mut entries: Vec<_> = handle.iter().collect();
+        entries.sort();
+        let expected: Vec<_> = (0..100)
+            .filter(|i| i % 3 != 0)
+            .map(|i| (i, i * 2))
+            .collect();
+        assert_eq!(entries, expected);
+
+        assert_eq!(handle.keys().count(), handle.len());
+        assert_eq!(
+            handle.values().sum::<i32>(),
+            expected.iter().map(|e| e.1).sum()
+        );
+    }
+
+    #[test]
+    fn hazard_iter_concurr() {
+        // keys that stay in the map must be seen exactly once, even while the keys the iterator
+        // is at are removed from under it
+        let mut handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            handle.insert(i * 2, i);
+        }
+
+        let mut writer = handle.clone();
+        let t = thread::spawn(move || {
+            for _ in 0..20 {
+                for i in 0..1000 {
+                    writer.insert(i * 2 + 1, i);
+                }
+                for i in 0..1000 {
+                    writer.remove(&(i * 2 + 1));
+                }
+            }
+        });
+
+        for _ in 0..20 {
+            let mut keys: Vec<_> = handle.keys().filter(|&k| k % 2 == 0).collect();
+            keys.sort();
+            assert_eq!(keys, (0..1000).map(|i| i * 2).collect::<Vec<_>>());
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn hazard_insert_race() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    let mut won = 0;
+                    let mut seen = Vec::new();
+                    for i in 0..1000 {
+                        if new_handle.try_insert(i, t).is_ok() {
+                            won += 1;
+                        }
+                        seen.push(new_handle.get_or_insert_with(i + 1000, || t));
+                    }
+                    (won, seen)
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        // every key was inserted by exactly one thread, and everyone saw the winner's value
+        let mut handle = handle;
+        assert_eq!(results.iter().map(|r| r.0).sum::<usize>(), 1000);
+        for (i, winner) in (0..1000).map(|i| (i, handle.get(&(i + 1000)).unwrap())) {
+            for r in &results {
+                assert_eq!(r.1[i], winner);
+            }
+        }
+        assert_eq!(handle.len(), 2000);
+    }
+
+    #[test]
+    fn hazard_update_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let nkeys = 64;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = i % nkeys;
+                        new_handle.compute(key, |v| Some(v.map_or(1, |v| v + 1)));
+                        new_handle.update(&key, |v| v + 1).unwrap();
+                        if i % 7 == 0 {
+                            new_handle.compute(key + nkeys, |v| match v {
+                                Some(_) => None,
+                                None => Some(0),
+                            });
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // no increment was lost
+        let mut handle = handle;
+        let total: usize = (0..nkeys).map(|i| handle.get(&i).unwrap()).sum();
+        assert_eq!(total, 2 * nthreads * 1000);
+        assert_eq!(handle.len(), handle.keys().count());
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct Key(usize);
+
+    impl Hash for Key {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            // few distinct hashes, so that removals have to unlink nodes in the middle of buckets
+            state.write_usize(self.0 % 7);
+        }
+    }
+
+    #[test]
+    fn hazard_compare_exchange_concurr() {
+        let mut handle = Map::with_capacity(1);
+        handle.insert(0, 0);
+        let nthreads = 8;
+        let n = 10000;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    let mut done = 0;
+                    while done < n {
+                        let cur = new_handle.get(&0).unwrap();
+                        if new_handle.compare_exchange(&0, &cur, cur + 1).is_ok() {
+                            done += 1;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // every successful exchange replaced the value it was given, so none of them was lost
+        assert_eq!(handle.get(&0), Some(nthreads * n));
+    }
+
+    #[test]
+    fn hazard_drop_frees_everything() {
+        let tracker = Arc::new(Tracker::default());
+        let handle = Map::with_capacity(2);
+        let nthreads = 4;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                let tracker = Arc::clone(&tracker);
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        new_handle.insert(Key(t * 200 + i), Tracked::new(&tracker));
+                    }
+                    for i in 0..200 {
+                        let key = Key(t * 200 + i);
+                        match i % 4 {
+                            0 => drop(new_handle.remove(&key)),
+                            1 => drop(new_handle.insert(key, Tracked::new(&tracker))),
+                            2 => drop(new_handle.try_insert(key, Tracked::new(&tracker))),
+                            _ => drop(new_handle.compute(key, |v| v.cloned())),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(handle.len(), nthreads * 150);
+
+        // every value, whether still in the map or retired, is dropped exactly once
+        drop(handle);
+        assert_eq!(tracker.live(), 0);
+    }
+
+    #[test]
+    fn hazard_handle_drop() {
+        let mut handle = Map::with_capacity(16);
+        for _ in 0..10 {
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    let mut new_handle = handle.clone();
+                    thread::spawn(move || {
+                        for i in 0..100 {
+                            let key = t * 100 + i;
+                            new_handle.insert(key, vec![key; 8]);
+                            new_handle.remove(&key);
+                        }
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+        }
+
+        // nothing is protected once the other handles are gone, so the next scan frees everything
+        // they left behind
+        assert_eq!(handle.map.handles.read().unwrap().len(), 1);
+        handle.scan();
+        assert_eq!(handle.map.orphans.lock().unwrap().len(), 0);
+        assert_eq!(handle.retired.len(), 0);
+        handle.insert(0, vec![]);
+        assert_eq!(handle.get_and(&0, Vec::len), Some(0));
+    }
+}
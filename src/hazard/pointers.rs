@@ -0,0 +1,105 @@
+use reclaim::SLOTS;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+const OSC: Ordering = Ordering::SeqCst;
+
+/// The hazard pointers of one handle.
+///
+/// A thread may only dereference a pointer it has loaded from the map after publishing it in one
+/// of its hazard pointers, and then checking that the pointer is still reachable from where it
+/// was loaded. Memory that is retired after that check will not be freed until the hazard
+/// pointer is cleared.
+#[derive(Default)]
+pub(super) struct Hazards {
+    slots: [AtomicUsize; SLOTS],
+}
+
+impl Hazards {
+    /// Publishes `ptr` in hazard pointer `slot`. The caller still has to validate that `ptr` was
+    /// not retired before it was published.
+    pub(super) fn protect<T>(&self, slot: usize, ptr: *mut T) {
+        self.slots[slot].store(ptr as usize, OSC);
+    }
+
+    /// Clears all of the hazard pointers, at the end of an operation.
+    pub(super) fn clear(&self) {
+        for slot in &self.slots {
+            slot.store(0, OSC);
+        }
+    }
+}
+
+/// The hazard pointers of all of the handles to a map.
+pub(super) type Registry = RwLock<Vec<Arc<Hazards>>>;
+
+/// Returns every pointer that is currently protected by a handle in `handles`, sorted.
+pub(super) fn protected(handles: &Registry) -> Vec<usize> {
+    let mut protected: Vec<_> = handles
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|h| h.slots.iter().map(|s| s.load(OSC)))
+        .filter(|&p| p != 0)
+        .collect();
+    protected.sort_unstable();
+    protected
+}
+
+/// Memory that has been unlinked from a map, but that may still be protected by a hazard pointer.
+///
+/// Dropping it frees everything in it.
+pub(super) struct Retired<N, V, B> {
+    pub(super) nodes: Vec<*mut N>,
+    pub(super) vals: Vec<*mut V>,
+    pub(super) buckets: Vec<*mut B>,
+}
+
+// retired memory is unreachable from the map, so it is owned exclusively
+unsafe impl<N: Send, V: Send, B: Send> Send for Retired<N, V, B> {}
+
+impl<N, V, B> Default for Retired<N, V, B> {
+    fn default() -> Self {
+        Retired {
+            nodes: Vec::new(),
+            vals: Vec::new(),
+            buckets: Vec::new(),
+        }
+    }
+}
+
+impl<N, V, B> Retired<N, V, B> {
+    pub(super) fn len(&self) -> usize {
+        self.nodes.len() + self.vals.len() + self.buckets.len()
+    }
+
+    /// Frees everything that is not in `protected`, which must be sorted.
+    pub(super) fn scan(&mut self, protected: &[usize]) {
+        fn free_unprotected<T>(retired: &mut Vec<*mut T>, protected: &[usize]) {
+            retired.retain(|&p| {
+                if protected.binary_search(&(p as usize)).is_ok() {
+                    return true;
+                }
+                drop(unsafe { Box::from_raw(p) });
+                false
+            });
+        }
+
+        free_unprotected(&mut self.nodes, protected);
+        free_unprotected(&mut self.vals, protected);
+        free_unprotected(&mut self.buckets, protected);
+    }
+
+    /// Moves everything in `other` into `self`.
+    pub(super) fn append(&mut self, other: &mut Self) {
+        self.nodes.append(&mut other.nodes);
+        self.vals.append(&mut other.vals);
+        self.buckets.append(&mut other.buckets);
+    }
+}
+
+impl<N, V, B> Drop for Retired<N, V, B> {
+    fn drop(&mut self) {
+        self.scan(&[]);
+    }
+}
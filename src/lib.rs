@@ -1,15 +1,23 @@
-//! This crate provides two implementations of fast, concurrent, shared hash maps.
+//! This crate provides three implementations of fast, concurrent, shared hash maps.
 //!
-//! Both implementations provide lock-free implementations that use [lock-free linked list
+//! All implementations provide lock-free implementations that use [lock-free linked list
 //! buckets](https://www.microsoft.com/en-us/research/wp-content/uploads/2001/10/2001-disc.pdf).
 //! Memory is safely destructed and reclaimed using either
-//! [`crossbeam::epoch`](https://docs.rs/crossbeam-epoch/) or a manual _Quiescent-State-Based
-//! Reclamation_ implementation. See the [`crossbeam`] and [`manual`] module documentations
-//! respectively for further details.
+//! [`crossbeam::epoch`](https://docs.rs/crossbeam-epoch/), a manual _Quiescent-State-Based
+//! Reclamation_ implementation, or hazard pointers. See the [`crossbeam`], [`manual`] and
+//! [`hazard`] module documentations respectively for further details. The hazard-pointer map
+//! trades some read throughput for a hard bound on how much memory is waiting to be reclaimed.
 //!
-//! Table resizing is not yet supported in either implementation, but the map will also never fill
-//! due to the linked implementation; instead, performance will decrease as the map is filled with
-//! more keys.
+//! All maps are built on the same table, and differ only in how they reclaim memory. They grow
+//! the table online as keys are inserted, using split-ordered lists so that no key ever has to
+//! move between buckets.
+//!
+//! Like `std::collections::HashMap`, all maps hash keys with a randomly seeded `RandomState` by
+//! default, so that an attacker cannot predict which keys will collide. A different hasher can be
+//! used by constructing a map with `with_capacity_and_hasher`.
+//!
+//! The handles of all maps implement [`ConcurrentMap`], so code that is generic over it can
+//! switch between the reclamation strategies with a type parameter.
 //!
 //! The crate was written by Aditya Saligrama and Andrew Shen while writing _A practical analysis
 //! of Rust’s concurrency story_ as their 2018 project for [MIT
@@ -18,7 +26,8 @@
 #![cfg_attr(feature = "bench", feature(test))]
 #![deny(missing_docs)]
 
-extern crate crossbeam as cx;
+extern crate crossbeam_epoch as epoch;
+extern crate thread_local;
 
 #[cfg(any(feature = "bench", test))]
 extern crate rand;
@@ -26,4 +35,267 @@ extern crate rand;
 extern crate test;
 
 pub mod crossbeam;
+pub mod hazard;
 pub mod manual;
+mod reclaim;
+mod table;
+#[cfg(test)]
+mod test_util;
+
+use std::time::Duration;
+
+/// The operations supported by the handles of all maps.
+///
+/// Implementors are handles to a shared map: cloning one gives another handle to the same map,
+/// which can be moved to another thread. Since no map can hand out plain references into the
+/// map, values are returned as clones.
+///
+/// # Examples
+///
+/// ```
+/// use concache::{crossbeam, hazard, manual, ConcurrentMap};
+///
+/// fn count_words<M: ConcurrentMap<String, usize>>(map: &mut M, text: &str) {
+///     for word in text.split_whitespace() {
+///         map.compute(word.to_owned(), |n| Some(n.map_or(1, |n| n + 1)));
+///     }
+/// }
+///
+/// let mut map = manual::Map::with_capacity(16);
+/// count_words(&mut map, "a b a");
+/// assert_eq!(map.get("a"), Some(2));
+///
+/// let mut map = crossbeam::Map::with_capacity(16);
+/// count_words(&mut map, "a b a");
+/// assert_eq!(map.get("a"), Some(2));
+///
+/// let mut map = hazard::Map::with_capacity(16);
+/// count_words(&mut map, "a b a");
+/// assert_eq!(map.get("a"), Some(2));
+/// ```
+pub trait ConcurrentMap<K, V>: Clone {
+    /// The iterator returned by [`ConcurrentMap::iter`].
+    type Iter<'a>: Iterator<Item = (K, V)>
+    where
+        Self: 'a;
+
+    /// Inserts a key-value pair into the map, and returns the value it replaced, if any.
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Returns a clone of the value corresponding to the key.
+    fn get(&mut self, key: &K) -> Option<V>;
+
+    /// Returns a clone of the value corresponding to the key, first inserting the value returned
+    /// by `make` if the key is not already present.
+    fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V;
+
+    /// Atomically replaces the value corresponding to the key with `f` applied to it, and returns
+    /// the new value. `f` may be called several times if the value changes concurrently.
+    fn update<F>(&mut self, key: &K, f: F) -> Option<V>
+    where
+        F: FnMut(&V) -> V;
+
+    /// Atomically sets the value corresponding to the key to `f` applied to the current value,
+    /// inserting or removing the key as needed, and returns the new value. `f` may be called
+    /// several times if the value changes concurrently.
+    fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>;
+
+    /// Removes a key from the map, and returns its value if the key was present.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Returns the number of elements in the map.
+    fn len(&self) -> usize;
+
+    /// Returns true if the map contains no elements.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// An iterator visiting clones of all key-value pairs in the map.
+    ///
+    /// The iterator is weakly consistent: every key that is in the map for the entire iteration is
+    /// yielded exactly once, while keys that are inserted or removed concurrently may or may not
+    /// be yielded.
+    fn iter(&mut self) -> Self::Iter<'_>;
+
+    /// Gets the entry for the given key, for in-place manipulation of its value.
+    fn entry(&mut self, key: K) -> Entry<'_, Self, K>
+    where
+        Self: Sized,
+    {
+        Entry { map: self, key }
+    }
+}
+
+/// A key in a [`ConcurrentMap`], which may or may not be present.
+///
+/// This `struct` is created by [`ConcurrentMap::entry`]. Each of its methods is atomic on its own,
+/// but since other threads may modify the key in between, a chain such as
+/// `entry.and_modify(f).or_insert(v)` is not. Use [`ConcurrentMap::compute`] when the whole
+/// operation has to be atomic.
+pub struct Entry<'a, M: 'a, K> {
+    map: &'a mut M,
+    key: K,
+}
+
+impl<'a, M, K> Entry<'a, M, K> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a clone of the key's value, first inserting `default` if the key is not present.
+    pub fn or_insert<V>(self, default: V) -> V
+    where
+        M: ConcurrentMap<K, V>,
+    {
+        self.map.get_or_insert_with(self.key, || default)
+    }
+
+    /// Returns a clone of the key's value, first inserting the value returned by `make` if the
+    /// key is not present.
+    pub fn or_insert_with<V, F>(self, make: F) -> V
+    where
+        M: ConcurrentMap<K, V>,
+        F: FnOnce() -> V,
+    {
+        self.map.get_or_insert_with(self.key, make)
+    }
+
+    /// Returns a clone of the key's value, first inserting `V::default()` if the key is not
+    /// present.
+    pub fn or_default<V>(self) -> V
+    where
+        M: ConcurrentMap<K, V>,
+        V: Default,
+    {
+        self.map.get_or_insert_with(self.key, V::default)
+    }
+
+    /// Replaces the key's value with `f` applied to it if the key is present, and returns the
+    /// entry for further use.
+    pub fn and_modify<V, F>(self, f: F) -> Self
+    where
+        M: ConcurrentMap<K, V>,
+        F: FnMut(&V) -> V,
+    {
+        self.map.update(&self.key, f);
+        self
+    }
+}
+
+/// A snapshot of how much memory a map is holding on to, as returned by the `stats` method of each
+/// map's handle.
+///
+/// A growing number of retired nodes and values means that memory is not being reclaimed as fast
+/// as it is being retired. See the `stats` method of each map for what exactly is counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of removed nodes that have not been freed yet.
+    pub retired_nodes: usize,
+    /// The number of replaced or removed values that have not been freed yet.
+    pub retired_values: usize,
+    /// The number of handles registered with the map.
+    pub handles: usize,
+    /// The number of times the map's handles have tried to free the garbage they retired.
+    pub cleanups: usize,
+    /// The total time the map's handles have spent waiting for other threads before they could
+    /// free garbage. Only [`manual::MapHandle::flush`] ever waits, so this is always zero for the
+    /// other maps.
+    pub wait_time: Duration,
+    /// Approximately how many bytes of heap the entries in the map take up, not counting memory
+    /// owned by the keys and values themselves.
+    pub live_bytes: usize,
+}
+
+/// How evenly the keys of a map are spread over its buckets, as returned by the `load_report`
+/// method of each map's handle.
+///
+/// A key belongs to the bucket that its hash selects among the buckets the map had when the
+/// report was taken. Since the maps grow to keep the average number of keys per bucket low, long
+/// chains point to keys whose hashes collide, rather than to a table that is too small.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// The number of buckets.
+    pub buckets: usize,
+    /// The distribution of chain lengths: `histogram[n]` is the number of buckets that hold
+    /// exactly `n` keys.
+    pub histogram: Vec<usize>,
+    /// The number of keys in the fullest bucket.
+    pub longest_chain: usize,
+    /// The number of buckets that hold no keys.
+    pub empty_buckets: usize,
+    /// The number of nodes that have been removed, but that have not been unlinked from the map
+    /// yet.
+    pub deleted_nodes: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn exercise<M>(map: M)
+    where
+        M: ConcurrentMap<usize, usize> + Send + 'static,
+    {
+        let mut map = map;
+        assert!(map.is_empty());
+        assert_eq!(map.insert(1, 10), None);
+        assert_eq!(map.insert(1, 11), Some(10));
+        assert_eq!(map.get(&1), Some(11));
+        assert_eq!(map.get_or_insert_with(2, || 20), 20);
+        assert_eq!(map.get_or_insert_with(2, || unreachable!()), 20);
+        assert_eq!(map.update(&2, |v| v + 1), Some(21));
+        assert_eq!(map.update(&3, |v| v + 1), None);
+        assert_eq!(map.compute(3, |v| Some(v.map_or(30, |v| v + 1))), Some(30));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.entry(4).or_default(), 0);
+        assert_eq!(map.entry(4).and_modify(|v| v + 40).or_insert(0), 40);
+        assert_eq!(map.entry(5).and_modify(|v| v + 1).or_insert_with(|| 50), 50);
+        assert_eq!(*map.entry(6).key(), 6);
+
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort();
+        assert_eq!(entries, vec![(1, 11), (2, 21), (3, 30), (4, 40), (5, 50)]);
+
+        assert_eq!(map.remove(&1), Some(11));
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.len(), 4);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mut map = map.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        map.compute(0, |v| Some(v.map_or(1, |v| v + 1)));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(map.get(&0), Some(4000));
+    }
+
+    #[test]
+    fn concurrent_map_manual() {
+        exercise(manual::Map::with_capacity(4));
+    }
+
+    #[test]
+    fn concurrent_map_crossbeam() {
+        exercise(crossbeam::Map::with_capacity(4));
+    }
+
+    #[test]
+    fn concurrent_map_hazard() {
+        exercise(hazard::Map::with_capacity(4));
+    }
+}
@@ -0,0 +1,149 @@
+use super::{Map, MapHandle, Reclaim, Reclaimer, Registry};
+use std::collections::hash_map::RandomState;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::sync::{Arc, Mutex, RwLock};
+use table::Table;
+
+/// A builder for a [`Map`] with a non-default configuration.
+///
+/// Every handle to the map collects the memory it retires, and periodically hands it to a
+/// cleanup that frees what no other thread can still be reading. By default, a handle cleans up
+/// once every 1000 operations. Write-heavy workloads can cap how much garbage a handle holds on
+/// to with [`Builder::max_pending`] or [`Builder::max_pending_bytes`], while read-heavy workloads,
+/// which retire little, can clean up less often with [`Builder::reclaim_every`]. Latency-sensitive
+/// workloads can leave freeing memory to a dedicated thread with
+/// [`Builder::reclaim_in_background`].
+///
+/// This `struct` is created by [`Map::builder`].
+///
+/// # Examples
+///
+/// ```
+/// use concache::manual::Map;
+///
+/// let mut map = Map::builder()
+///     .capacity(1024)
+///     .reclaim_every(10_000)
+///     .max_pending_bytes(1 << 20)
+///     .build();
+/// map.insert(1, "a");
+/// assert_eq!(map.get(&1), Some("a"));
+/// ```
+pub struct Builder<K, V, S = RandomState> {
+    nbuckets: usize,
+    hash_builder: S,
+    reclaim: Reclaim,
+    handles: Arc<Registry>,
+    reclaimer: Option<Reclaimer<K, V>>,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Builder<K, V, RandomState> {
+    pub(super) fn new() -> Self {
+        Builder {
+            nbuckets: 16,
+            hash_builder: RandomState::new(),
+            reclaim: Reclaim::default(),
+            handles: Arc::new(RwLock::new(Vec::new())),
+            reclaimer: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> Builder<K, V, S> {
+    /// Sets the number of buckets the map starts out with.
+    ///
+    /// See [`Map::with_capacity`] for the meaning of `nbuckets`. Defaults to 16.
+    pub fn capacity(mut self, nbuckets: usize) -> Self {
+        self.nbuckets = nbuckets;
+        self
+    }
+
+    /// Sets the hasher used to hash keys.
+    ///
+    /// Defaults to a [`RandomState`] that is seeded differently for every map.
+    pub fn hasher<T>(self, hash_builder: T) -> Builder<K, V, T> {
+        Builder {
+            nbuckets: self.nbuckets,
+            hash_builder,
+            reclaim: self.reclaim,
+            handles: self.handles,
+            reclaimer: self.reclaimer,
+            marker: PhantomData,
+        }
+    }
+
+    /// Makes each handle clean up after every `n` operations on it.
+    ///
+    /// Defaults to 1000. Passing `usize::MAX` leaves cleanups to the limits on pending garbage.
+    pub fn reclaim_every(mut self, n: usize) -> Self {
+        self.reclaim.every = n;
+        self
+    }
+
+    /// Makes a handle clean up as soon as it has retired `n` nodes, values or bucket directories
+    /// since its last cleanup.
+    ///
+    /// There is no limit by default.
+    pub fn max_pending(mut self, n: usize) -> Self {
+        self.reclaim.max_pending = n;
+        self
+    }
+
+    /// Makes a handle clean up as soon as the garbage it has retired since its last cleanup takes
+    /// up `bytes` bytes.
+    ///
+    /// Only the memory the map allocated itself is counted, and not any memory that is owned by
+    /// the retired keys and values. There is no limit by default.
+    pub fn max_pending_bytes(mut self, bytes: usize) -> Self {
+        self.reclaim.max_pending_bytes = bytes;
+        self
+    }
+
+    /// Creates the map, and returns a handle to it.
+    pub fn build(self) -> MapHandle<K, V, S> {
+        let new_hashmap = Map {
+            table: Table::new(self.nbuckets),
+            hash_builder: self.hash_builder,
+            handles: self.handles,
+            orphans: Mutex::new(Vec::new()),
+            reclaim: self.reclaim,
+            reclaimer: self.reclaimer,
+            cleanups: AtomicUsize::new(0),
+            wait_nanos: AtomicU64::new(0),
+        };
+        MapHandle::register(Arc::new(new_hashmap))
+    }
+}
+
+impl<K, V, S> Builder<K, V, S>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    /// Starts a thread that frees the memory retired by the map's handles.
+    ///
+    /// Instead of freeing their garbage themselves, handles then just pass it on to the thread
+    /// when they clean up, which is cheap and never has to look at other handles. The thread waits
+    /// until no other thread can be reading the garbage before freeing it. When the map is
+    /// dropped, the thread frees everything it still holds and exits, and the drop waits for it
+    /// to do so.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::builder().reclaim_in_background().build();
+    /// map.insert(1, String::from("a"));
+    /// map.remove(&1);
+    /// ```
+    pub fn reclaim_in_background(mut self) -> Self {
+        if self.reclaimer.is_none() {
+            self.reclaimer = Some(Reclaimer::spawn(Arc::clone(&self.handles)));
+        }
+        self
+    }
+}
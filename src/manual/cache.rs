@@ -0,0 +1,296 @@
+use super::{Map, MapHandle};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use table::MAX_LOAD_FACTOR;
+
+/// A [`Map`] that holds at most a fixed number of entries, for use as a bounded in-memory cache.
+///
+/// Once the cache is full, inserting a new key evicts a key that has not been used recently,
+/// following the [CLOCK](https://en.wikipedia.org/wiki/Page_replacement_algorithm#Clock)
+/// approximation of least-recently-used eviction. Every entry has a reference bit that is set
+/// whenever its key is found, which includes inserts that replace its value. A hand that all
+/// handles share sweeps over the buckets, clearing the bits it passes, and evicts the first key
+/// whose bit was already clear. Unlike with a true LRU list, looking up a key only writes to
+/// shared memory if the hand has passed the key since it was last found.
+///
+/// Like a [`MapHandle`], a `Cache` is a handle to a shared cache, and needs `&mut self` for every
+/// operation. To access the cache from another thread, clone it.
+///
+/// Concurrent inserts may briefly take the cache past its capacity, and may together evict more
+/// keys than needed to get it back under. Evictions alone never take a full cache below
+/// `capacity - 2 * n + 1` entries, where `n` is the number of threads using it at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use concache::manual::Cache;
+///
+/// let mut cache = Cache::new(2);
+/// cache.insert("a", 1);
+/// cache.insert("b", 2);
+///
+/// // one of "a" and "b" is evicted to make room
+/// cache.insert("c", 3);
+/// assert_eq!(cache.len(), 2);
+/// assert_eq!(cache.get("c"), Some(3));
+/// assert!(cache.contains_key("a") != cache.contains_key("b"));
+/// ```
+pub struct Cache<K, V, S = RandomState> {
+    handle: MapHandle<K, V, S>,
+    capacity: usize,
+}
+
+impl<K, V> Cache<K, V, RandomState> {
+    /// Creates a new, shared cache that holds at most `capacity` entries, and returns a handle to
+    /// it.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> Cache<K, V, S> {
+    /// Creates a new, shared cache that holds at most `capacity` entries, and uses
+    /// `hash_builder` to hash keys.
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        Cache {
+            handle: Map::with_capacity_and_hasher(capacity / MAX_LOAD_FACTOR, hash_builder),
+            capacity,
+        }
+    }
+
+    /// Returns the maximum number of entries the cache holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.handle.map.table.len()
+    }
+
+    /// Returns true if the cache contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Inserts a key-value pair into the cache, and returns a clone of the value it replaced, if
+    /// any.
+    ///
+    /// If the key is new and the cache is full, keys that have not been used recently are evicted
+    /// first to make room for it. Only if other threads fill the cache up again while the key is
+    /// being inserted may the key itself be evicted right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Cache;
+    ///
+    /// let mut cache = Cache::new(16);
+    /// assert_eq!(cache.insert(37, "a"), None);
+    /// assert_eq!(cache.insert(37, "b"), Some("a"));
+    /// assert_eq!(cache.get(&37), Some("b"));
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let capacity = self.capacity;
+        let hash = self.handle.map.hash(&key);
+
+        self.handle.operate(|table, r| {
+            // make room before inserting, so that the hand cannot come across the new key and
+            // evict it straight away
+            if table.get(r, hash, &key).is_none() {
+                while table.len() >= capacity && table.evict(r).is_some() {}
+            }
+            let ret = table
+                .insert(r, hash, key, value)
+                .map(|v| unsafe { (*v).clone() });
+            // other threads may have filled the cache up in the meantime
+            while table.len() > capacity && table.evict(r).is_some() {}
+            ret
+        })
+    }
+
+    /// Returns `true` if the cache contains a value for the key, and marks the key as used.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq` on the
+    /// borrowed form *must* match those for the key type.
+    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.handle.contains_key(key)
+    }
+
+    /// Returns a copy of the value corresponding to the key, and marks the key as used.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq` on the
+    /// borrowed form *must* match those for the key type.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Copy,
+    {
+        self.handle.get(key)
+    }
+
+    /// Returns a clone of the value corresponding to the key, and marks the key as used.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq` on the
+    /// borrowed form *must* match those for the key type.
+    pub fn get_cloned<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.handle.get_cloned(key)
+    }
+
+    /// Applies a function to the value corresponding to the key, returns the result, and marks
+    /// the key as used.
+    ///
+    /// See [`MapHandle::get_and`] for how long the function may hold on to the value.
+    pub fn get_and<Q, F, R>(&mut self, key: &Q, then: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        self.handle.get_and(key, then)
+    }
+
+    /// Removes a key from the cache, returning a clone of the value if the key was present.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq` on the
+    /// borrowed form *must* match those for the key type.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.handle.remove(key)
+    }
+}
+
+impl<K, V, S> Clone for Cache<K, V, S> {
+    fn clone(&self) -> Self {
+        Cache {
+            handle: self.handle.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn cache_bounded() {
+        let mut cache = Cache::new(100);
+        for key in 0..1000 {
+            assert_eq!(cache.insert(key, key), None);
+            assert!(cache.len() <= 100);
+            assert_eq!(cache.get(&key), Some(key));
+        }
+        assert_eq!(cache.len(), 100);
+
+        // replacing a value does not evict anything
+        let present: Vec<_> = (0..1000).filter(|k| cache.contains_key(k)).collect();
+        assert_eq!(present.len(), 100);
+        for &key in &present {
+            assert_eq!(cache.insert(key, key + 1), Some(key));
+        }
+        assert_eq!(cache.len(), 100);
+    }
+
+    #[test]
+    fn cache_keeps_hot_keys() {
+        let mut cache = Cache::new(64);
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+
+        for key in 0..8 {
+            cache.insert(key, key);
+        }
+        for key in 200..2000 {
+            cache.insert(key, key);
+            for hot in 0..8 {
+                assert_eq!(cache.get(&hot), Some(hot));
+            }
+        }
+        assert_eq!(cache.len(), 64);
+    }
+
+    #[test]
+    fn cache_replace_is_use() {
+        let mut cache = Cache::new(64);
+        for key in 100..200 {
+            cache.insert(key, key);
+        }
+
+        for key in 0..8 {
+            cache.insert(key, key);
+        }
+        // replacing the value of a key marks the key as used, just like finding it does
+        for key in 200..2000 {
+            cache.insert(key, key);
+            for hot in 0..8 {
+                assert_eq!(cache.insert(hot, hot), Some(hot));
+            }
+        }
+        assert_eq!(cache.len(), 64);
+    }
+
+    #[test]
+    fn cache_zero_capacity() {
+        let mut cache = Cache::new(0);
+        cache.insert(1, 1);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn cache_concurr() {
+        let nthreads = 8;
+        let cache = Cache::new(500);
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..2000 {
+                        let key = t * 2000 + i;
+                        cache.insert(key, key);
+                        if let Some(v) = cache.get(&(key / 2)) {
+                            assert_eq!(v, key / 2);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // concurrent inserts never leave too many keys behind. the count only drops after a key is
+        // unlinked, so a thread can see it one too high for every other thread that is evicting,
+        // and start one eviction too many itself. a full cache thus never drops below
+        // `500 - (nthreads - 1) - nthreads` keys.
+        assert!(cache.len() <= 500);
+        assert!(cache.len() > 500 - 2 * nthreads, "{}", cache.len());
+    }
+}
@@ -3,102 +3,208 @@
 //! This implementation provides a lock-free hash map using buckets that hold [lock-free linked
 //! lists](https://www.microsoft.com/en-us/research/wp-content/uploads/2001/10/2001-disc.pdf).
 //! Memory is safely destructed and reclaimed using a simplified variant of _Quiescent-State-Based
-//! Reclamation_. Table resizing is not yet supported, but the map will also never fill due to the
-//! linked implementation; instead, performance will decrease as the map is filled with more keys.
+//! Reclamation_. Each handle frees its garbage in batches, once every thread that was in the middle
+//! of an operation when a batch was retired has finished that operation. Reclamation never waits
+//! for other threads, so a thread that stalls in the middle of an operation only delays when memory
+//! is freed, and never holds up operations on other threads. When and where garbage is freed can
+//! be configured with a [`Builder`], including on a dedicated background thread.
+//!
+//! All keys are kept in a single list ordered by their bit-reversed hashes, and buckets are
+//! shortcuts into that list ([split-ordered lists](https://dl.acm.org/citation.cfm?id=1147958)).
+//! When the average number of keys per bucket grows too large, the number of buckets doubles, and
+//! each new bucket is split off its parent the first time an operation touches it. Nodes never
+//! move during this process, so all operations remain lock-free while the table grows.
 //!
 //! The interface to this map is somewhat different from `HashMap` to support concurrent operation.
 //! When you create a new [`Map`],you are given a [`MapHandle`], which allows access to the map's
 //! data. To read or mutate the map for elsewhere, you call [`MapHandle::clone`], which gives you
 //! a new `MapHandle` that provides concurrent access to the same map.
 //!
+//! Since a `MapHandle` needs `&mut self` for every operation, it cannot be shared between threads
+//! by reference. A [`SharedMap`] wraps the map and keeps one `MapHandle` per thread instead, so that
+//! it can be used through `&self` from behind an `Arc`.
+//!
+//! A [`Cache`] is a map with a maximum number of entries, which makes room for new keys by
+//! evicting keys that have not been used recently.
+//!
 //! Similarly to [`crossbeam::epoch`](https://docs.rs/crossbeam-epoch/), this `Map` does not
 //! guarantee that destructors are called. In practice though, as long as threads do not leak
 //! `MapHandle`s, destructors will all eventually be called.
 //!
-//! Note that unlike `HashMap`, this `Map` requires its values to be `Copy`. This greatly
-//! simplifies the map's interface; accesses to the map's data have to be carefully guarded, and
-//! there is no simple way to expose references into the map through a method call. Later, we may
-//! provide temporary access through closures, similar to `evmap`'s
-//! [`ReadHandle::get_and`](https://docs.rs/evmap/4/evmap/struct.ReadHandle.html#method.get_and),
-//! but for the time being, values have to be `Copy`.
-
-use std::collections::hash_map::DefaultHasher;
-use std::fmt::Debug;
-use std::hash::{Hash, Hasher};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+//! Note that unlike `HashMap`, this `Map` cannot hand out references into the map through a method
+//! call, since accesses to the map's data have to be carefully guarded. Instead, values can be
+//! read by copying them out with [`MapHandle::get`], by cloning them with
+//! [`MapHandle::get_cloned`], or through a closure that is given temporary access to the value,
+//! similar to `evmap`'s
+//! [`ReadHandle::get_and`](https://docs.rs/evmap/4/evmap/struct.ReadHandle.html#method.get_and).
+
+use reclaim::{self, Reclaimer as _};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
+use table::{self, Buckets, Cursor as TableCursor, Node, Table};
+use {ConcurrentMap, LoadReport, Stats};
 
-mod linked_list;
-use self::linked_list::{LinkedList, Node};
+mod builder;
+pub use self::builder::Builder;
+
+mod cache;
+pub use self::cache::Cache;
+
+mod reclaimer;
+use self::reclaimer::Reclaimer;
+
+mod shared;
+pub use self::shared::SharedMap;
 
 const OSC: Ordering = Ordering::SeqCst;
 const REFRESH_RATE: usize = 1000;
 
-struct Table<K, V> {
-    nbuckets: usize,
-    map: Vec<LinkedList<K, V>>,
-    nitems: AtomicUsize,
+/// The garbage a handle has retired since its last cleanup.
+struct Retired<K, V> {
+    nodes: Vec<*mut Node<K, V>>,
+    vals: Vec<*mut V>,
+    buckets: Vec<*mut Buckets<K, V>>,
 }
 
-impl<K, V> Table<K, V> {
-    fn new(num_of_buckets: usize) -> Self {
-        let mut t = Table {
-            nbuckets: num_of_buckets,
-            map: Vec::with_capacity(num_of_buckets),
-            nitems: AtomicUsize::new(0),
-        };
-
-        for _ in 0..num_of_buckets {
-            t.map.push(LinkedList::default());
+impl<K, V> Retired<K, V> {
+    fn new() -> Self {
+        Retired {
+            nodes: Vec::new(),
+            vals: Vec::new(),
+            buckets: Vec::new(),
         }
+    }
 
-        t
+    fn len(&self) -> usize {
+        self.nodes.len() + self.vals.len() + self.buckets.len()
     }
 }
 
-impl<K, V> Table<K, V>
-where
-    K: Hash + Ord,
-    V: Copy + Debug,
-{
-    fn insert(&self, key: K, value: V, remove_nodes: &mut Vec<*mut Node<K, V>>) -> Option<*mut V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let hash: usize = hasher.finish() as usize;
-        let index = hash % self.nbuckets;
+/// The parts of a handle that an operation on the table needs: the counter that announces its
+/// critical sections, and the garbage to retire what it unlinks into.
+struct Local<'a, K: 'a, V: 'a> {
+    epoch: &'a AtomicUsize,
+    retired: &'a mut Retired<K, V>,
+}
 
-        let ret = self.map[index].insert(key, value, remove_nodes);
+// the handle's own critical section keeps everything it can reach alive until it exits, so
+// nothing has to be protected, and garbage only needs to be collected for the next cleanup
+impl<'a, K, V> reclaim::Reclaimer<K, V> for Local<'a, K, V> {
+    fn enter(&mut self) {
+        self.epoch.fetch_add(1, OSC);
+    }
 
-        if ret.is_none() {
-            self.nitems.fetch_add(1, OSC);
-        }
+    fn exit(&mut self) {
+        self.epoch.fetch_add(1, OSC);
+    }
 
-        ret
+    fn protect<T>(&mut self, _: usize, _: *mut T) {}
+
+    fn load<T>(&mut self, _: usize, src: &AtomicPtr<T>) -> *mut T {
+        src.load(OSC)
+    }
+
+    fn retire_node(&mut self, node: *mut Node<K, V>) {
+        self.retired.nodes.push(node);
     }
 
-    fn get(&self, key: &K, remove_nodes: &mut Vec<*mut Node<K, V>>) -> Option<V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let hash: usize = hasher.finish() as usize;
-        let index = hash % self.nbuckets;
+    fn retire_value(&mut self, value: *mut V) {
+        self.retired.vals.push(value);
+    }
 
-        self.map[index].get(key, remove_nodes)
+    fn retire_buckets(&mut self, buckets: *mut Buckets<K, V>) {
+        self.retired.buckets.push(buckets);
     }
+}
+
+/// Garbage retired by a handle, along with the critical sections that were in progress when it
+/// was retired.
+///
+/// Any thread that can still be reading the garbage must have been in one of those critical
+/// sections, so the batch can be freed once every one of them has ended. Dropping a batch frees
+/// everything in it.
+struct Batch<K, V> {
+    pending: Vec<(Arc<AtomicUsize>, usize)>,
+    nodes: Vec<*mut Node<K, V>>,
+    vals: Vec<*mut V>,
+    buckets: Vec<*mut Buckets<K, V>>,
+}
 
-    fn delete(&self, key: &K, remove_nodes: &mut Vec<*mut Node<K, V>>) -> Option<V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let hash: usize = hasher.finish() as usize;
-        let index = hash % self.nbuckets;
+// the garbage in a batch is unreachable from the map, so the batch owns it exclusively
+unsafe impl<K: Send, V: Send> Send for Batch<K, V> {}
 
-        let ret = self.map[index].delete(key, remove_nodes);
+impl<K, V> Batch<K, V> {
+    /// Returns true once no thread can be reading the garbage in this batch anymore.
+    fn is_safe(&self) -> bool {
+        // counters only ever go up, so a handle has left its critical section as soon as its
+        // counter has moved
+        self.pending.iter().all(|(h, epoch)| h.load(OSC) != *epoch)
+    }
+}
 
-        if ret.is_some() {
-            self.nitems.fetch_sub(1, OSC);
+impl<K, V> Drop for Batch<K, V> {
+    fn drop(&mut self) {
+        for &to_drop in &self.nodes {
+            // the value of a removed node has already been taken out by whoever removed it
+            drop(unsafe { Box::from_raw(to_drop) });
+        }
+        for &to_drop in &self.vals {
+            drop(unsafe { Box::from_raw(to_drop) });
         }
+        for &to_drop in &self.buckets {
+            drop(unsafe { Box::from_raw(to_drop) });
+        }
+    }
+}
 
-        ret
+/// The epoch counters of all the handles to a map.
+type Registry = RwLock<Vec<Arc<AtomicUsize>>>;
+
+/// Returns the handles in `handles` that are in a critical section right now, along with the
+/// value of their counters.
+fn in_critical_section(handles: &Registry) -> Vec<(Arc<AtomicUsize>, usize)> {
+    handles
+        .read()
+        .unwrap()
+        .iter()
+        .map(|h| (Arc::clone(h), h.load(OSC)))
+        .filter(|&(_, epoch)| epoch % 2 == 1)
+        .collect()
+}
+
+/// When handles clean up, as configured through a [`Builder`].
+#[derive(Clone, Copy)]
+struct Reclaim {
+    /// Clean up after this many operations.
+    every: usize,
+    /// Clean up once this many nodes, values and bucket directories have been retired.
+    max_pending: usize,
+    /// Clean up once the retired garbage takes up this many bytes.
+    max_pending_bytes: usize,
+}
+
+impl Default for Reclaim {
+    fn default() -> Self {
+        Reclaim {
+            every: REFRESH_RATE,
+            max_pending: usize::MAX,
+            max_pending_bytes: usize::MAX,
+        }
+    }
+}
+
+/// Frees the batches in `limbo` that no thread can be reading anymore.
+fn reclaim<K, V>(limbo: &mut Vec<Batch<K, V>>) {
+    limbo.retain(|b| !b.is_safe());
+    if limbo.is_empty() {
+        // don't hold on to the memory of a burst of garbage
+        *limbo = Vec::new();
     }
 }
 
@@ -106,82 +212,179 @@ where
 ///
 /// Any operation performed on this handle affects the map seen by all other related `MapHandle`
 /// instances. To get another handle to the `Map`, simply clone any of its handles.
-pub struct MapHandle<K, V> {
-    map: Arc<Map<K, V>>,
+pub struct MapHandle<K, V, S = RandomState> {
+    map: Arc<Map<K, V, S>>,
     epoch_counter: Arc<AtomicUsize>,
-    remove_nodes: Vec<*mut Node<K, V>>,
-    remove_val: Vec<*mut V>,
+    retired: Retired<K, V>,
+    limbo: Vec<Batch<K, V>>,
     refresh: usize,
+    offline: bool,
 }
 
-unsafe impl<K, V> Send for MapHandle<K, V>
+unsafe impl<K, V, S> Send for MapHandle<K, V, S>
 where
     K: Send + Sync,
-    V: Send + Debug,
+    V: Send + Sync,
+    S: Send + Sync,
 {
 }
 
-impl<K, V> MapHandle<K, V>
-where
-    V: Debug,
-{
-    fn cleanup(&mut self) {
-        //epoch set up, load all of the values
-        let mut started = Vec::new();
-        let handles_map = self.map.handles.read().unwrap();
-        for h in handles_map.iter() {
-            started.push(h.load(OSC));
-        }
-        for (i, h) in handles_map.iter().enumerate() {
-            if started[i] % 2 == 0 {
-                continue;
-            }
-            let mut check = h.load(OSC);
-            let mut iter = 0;
-            while (check <= started[i]) && (check % 2 == 1) {
-                if iter % 4 == 0 {
-                    // we may be waiting for a thread that isn't currently running
-                    thread::yield_now();
-                }
-                check = h.load(OSC);
-                iter += 1;
-                //do nothing, epoch spinning
-            }
+impl<K, V, S> MapHandle<K, V, S> {
+    /// Creates a new handle to `map`, and adds it to the epoch system.
+    fn register(map: Arc<Map<K, V, S>>) -> Self {
+        let ret = MapHandle {
+            map,
+            epoch_counter: Arc::new(AtomicUsize::new(0)),
+            retired: Retired::new(),
+            limbo: Vec::new(),
+            refresh: 0,
+            offline: false,
+        };
+
+        let mut handles_vec = ret.map.handles.write().unwrap(); //handles vector
+        handles_vec.push(Arc::clone(&ret.epoch_counter));
+        drop(handles_vec);
+
+        ret
+    }
+
+    fn local(&mut self) -> Local<'_, K, V> {
+        Local {
+            epoch: &self.epoch_counter,
+            retired: &mut self.retired,
+        }
+    }
+
+    /// Gets ready for an operation: brings the handle back online if it was taken offline, and
+    /// counts the operation towards the next cleanup.
+    fn start(&mut self) {
+        if self.offline {
+            self.online();
+        }
+        self.refresh += 1;
+    }
+
+    /// Reclaims garbage after an operation, if the map's [`Reclaim`] limits say so.
+    fn finish(&mut self) {
+        if self.refresh >= self.map.reclaim.every || self.too_much_pending() {
+            self.refresh = 0;
+            self.cleanup();
+        }
+    }
+
+    /// Enters a critical section; no node or value reachable from the map will be freed by
+    /// another handle until the matching call to [`MapHandle::exit`].
+    fn enter(&mut self) {
+        self.start();
+        self.local().enter();
+    }
+
+    /// Leaves a critical section, and reclaims garbage if the map's [`Reclaim`] limits say so.
+    fn exit(&mut self) {
+        self.local().exit();
+        self.finish();
+    }
+
+    /// Runs a single operation `f` on the map's table, in a critical section of its own.
+    fn operate<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&Table<K, V>, &mut Local<'_, K, V>) -> R,
+    {
+        self.start();
+        let mut local = Local {
+            epoch: &self.epoch_counter,
+            retired: &mut self.retired,
+        };
+        let table = &self.map.table;
+        let ret = reclaim::critical(&mut local, |r| f(table, r));
+        self.finish();
+
+        ret
+    }
+
+    /// Returns true if the garbage retired since the last cleanup exceeds the map's limits.
+    fn too_much_pending(&self) -> bool {
+        let reclaim = &self.map.reclaim;
+        let pending = self.retired.len();
+        if pending == 0 {
+            return false;
+        }
+        if pending >= reclaim.max_pending {
+            return true;
+        }
+        if reclaim.max_pending_bytes == usize::MAX {
+            return false;
         }
 
-        //physical deletion, epoch has rolled over so we are safe to proceed with physical deletion
-        //epoch rolled over, so we know we have exclusive access to the node
+        let buckets: usize = self
+            .retired
+            .buckets
+            .iter()
+            .map(|&b| unsafe { &*b }.size())
+            .sum();
+        let bytes = self.retired.nodes.len() * mem::size_of::<Node<K, V>>()
+            + self.retired.vals.len() * mem::size_of::<V>()
+            + buckets;
+        bytes >= reclaim.max_pending_bytes
+    }
 
-        // println!("{:?}", &self.remove_nodes.len());
-        for to_drop in &self.remove_nodes {
-            let n = unsafe { (&**to_drop).val.load(OSC) };
-            self.remove_val.push(n);
-            //[drop the value inside of the node, or add to remove_val]
-            drop(unsafe { Box::from_raw(*to_drop) });
+    /// Moves the garbage retired since the last cleanup into a new batch, and frees every batch
+    /// that no thread can be reading anymore.
+    ///
+    /// This never waits for other threads: a batch that is still needed by a thread that is in a
+    /// long critical section is simply kept around until a later cleanup.
+    fn cleanup(&mut self) {
+        self.map.cleanups.fetch_add(1, OSC);
+        if self.retired.len() != 0 {
+            let retired = mem::replace(&mut self.retired, Retired::new());
+            let garbage = Batch {
+                pending: Vec::new(),
+                nodes: retired.nodes,
+                vals: retired.vals,
+                buckets: retired.buckets,
+            };
+            // with a background reclaimer, freeing the garbage is up to it
+            let unsent = match self.map.reclaimer {
+                Some(ref reclaimer) => reclaimer.retire(garbage).err(),
+                None => Some(garbage),
+            };
+            if let Some(mut garbage) = unsent {
+                // only handles that are in a critical section right now can have seen the garbage
+                garbage.pending = in_critical_section(&self.map.handles);
+                self.limbo.push(garbage);
+            }
         }
 
-        for to_drop in &self.remove_val {
-            drop(unsafe { Box::from_raw(*to_drop) });
+        reclaim(&mut self.limbo);
+
+        // help with the garbage left behind by handles that have been dropped
+        if let Ok(mut orphans) = self.map.orphans.try_lock() {
+            reclaim(&mut orphans);
         }
+    }
 
-        //reset
-        self.remove_nodes = Vec::new();
-        self.remove_val = Vec::new();
+    /// Takes over the garbage retired by `other`, so that it is freed by this handle's cleanups
+    /// instead.
+    fn adopt(&mut self, other: &mut Self) {
+        self.retired.nodes.append(&mut other.retired.nodes);
+        self.retired.vals.append(&mut other.retired.vals);
+        self.retired.buckets.append(&mut other.retired.buckets);
+        self.limbo.append(&mut other.limbo);
     }
 }
 
-impl<K, V> MapHandle<K, V>
+impl<K, V, S> MapHandle<K, V, S>
 where
-    K: Hash + Ord,
-    V: Copy + Debug,
+    K: Hash + Eq,
+    S: BuildHasher,
 {
     /// Inserts a key-value pair into the map.
     ///
     /// If the map did not have this key present, `None` is returned.
     ///
-    /// If the map did have this key present, the value is updated, and the old value is returned.
-    /// The key is not updated, though; this matters for types that can be `==` without being
-    /// identical.
+    /// If the map did have this key present, the value is updated, and a clone of the old value is
+    /// returned. The key is not updated, though; this matters for types that can be `==` without
+    /// being identical.
     ///
     /// # Examples
     ///
@@ -196,30 +399,125 @@ where
     /// assert_eq!(map.insert(37, "c"), Some("b"));
     /// assert_eq!(map.get(&37), Some("c"));
     /// ```
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        self.refresh = self.refresh + 1;
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
 
-        self.epoch_counter.fetch_add(1, OSC);
-        let val = self.map.table.insert(key, value, &mut self.remove_nodes);
-        self.epoch_counter.fetch_add(1, OSC);
+        self.operate(|table, r| {
+            table
+                .insert(r, hash, key, value)
+                .map(|v| unsafe { (*v).clone() })
+        })
+    }
 
-        let mut ret = None;
+    /// Inserts a key-value pair into the map only if the key is not already present.
+    ///
+    /// Returns `Ok(())` if the value was inserted, and gives the value back as `Err(value)` if the
+    /// key was already present. Of several threads racing to insert the same key, exactly one
+    /// succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.try_insert(37, "a"), Ok(()));
+    /// assert_eq!(map.try_insert(37, "b"), Err("b"));
+    /// assert_eq!(map.get(&37), Some("a"));
+    /// ```
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<(), V> {
+        let mut value = Some(value);
+        let hash = self.map.hash(&key);
 
-        if let Some(v) = val {
-            ret = Some(unsafe { *v });
-            // drop(unsafe { Box::from_raw(v) });
-            self.remove_val.push(v);
-        }
+        let ret = self.operate(|table, r| table.insert_new(r, hash, key, || value.take().unwrap()));
 
-        if self.refresh == REFRESH_RATE {
-            self.refresh = 0;
-            self.cleanup();
+        match ret {
+            Ok(_) => Ok(()),
+            Err((_, unused)) => Err(unused.or(value).unwrap()),
         }
+    }
 
-        ret
+    /// Returns a clone of the value corresponding to the key, first inserting `value` if the key
+    /// is not already present.
+    ///
+    /// Of several threads racing to insert the same key, exactly one succeeds, and all of them
+    /// return the value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert(37, "a"), "a");
+    /// assert_eq!(map.get_or_insert(37, "b"), "a");
+    /// ```
+    pub fn get_or_insert(&mut self, key: K, value: V) -> V
+    where
+        V: Clone,
+    {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting the value returned
+    /// by `make` if the key is not already present.
+    ///
+    /// `make` is only called if the key is not present, but if another thread inserts the same key
+    /// concurrently, the value it returned may be dropped without ever being inserted. Of several
+    /// threads racing to insert the same key, exactly one succeeds, and all of them return the
+    /// value it inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.get_or_insert_with(37, || String::from("a")), "a");
+    /// assert_eq!(map.get_or_insert_with(37, || unreachable!()), "a");
+    /// ```
+    pub fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
+
+        self.operate(|table, r| match table.insert_new(r, hash, key, make) {
+            Ok(v) | Err((v, _)) => unsafe { (*v).clone() },
+        })
+    }
+
+    /// Returns `true` if the map contains a value for the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(String::from("a"), 1);
+    /// assert!(map.contains_key("a"));
+    /// assert!(!map.contains_key("b"));
+    /// ```
+    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_and(key, |_| ()).is_some()
     }
 
-    /// Returns a reference to the value corresponding to the key.
+    /// Returns a copy of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
     ///
     /// # Examples
     ///
@@ -231,23 +529,187 @@ where
     /// assert_eq!(map.get(&1), Some("a"));
     /// assert_eq!(map.get(&2), None);
     /// ```
-    pub fn get(&mut self, key: &K) -> Option<V> {
-        self.refresh = self.refresh + 1;
+    pub fn get<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Copy,
+    {
+        self.get_and(key, |v| *v)
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, String::from("a"));
+    /// assert_eq!(map.get_cloned(&1), Some(String::from("a")));
+    /// assert_eq!(map.get_cloned(&2), None);
+    /// ```
+    pub fn get_cloned<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.get_and(key, V::clone)
+    }
 
-        self.epoch_counter.fetch_add(1, OSC);
-        let ret = self.map.table.get(key, &mut self.remove_nodes);
-        self.epoch_counter.fetch_add(1, OSC);
+    /// Applies a function to the value corresponding to the key, and returns the result.
+    ///
+    /// The function is called while this handle is in a critical section, so the value cannot be
+    /// freed while the function is accessing it. Long-running functions delay memory reclamation
+    /// for all handles to the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, vec![1, 2, 3]);
+    /// assert_eq!(map.get_and(&1, |v| v.len()), Some(3));
+    /// assert_eq!(map.get_and(&2, |v| v.len()), None);
+    /// ```
+    pub fn get_and<Q, F, R>(&mut self, key: &Q, then: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        let hash = self.map.hash(key);
 
-        if self.refresh == REFRESH_RATE {
-            self.refresh = 0;
-            self.cleanup();
-        }
+        self.operate(|table, r| table.get(r, hash, key).map(|v| then(unsafe { &*v })))
+    }
 
-        ret
+    /// Atomically replaces the value corresponding to the key with the result of applying `f` to
+    /// it, and returns a clone of the new value. Returns `None`, without calling `f`, if the key is
+    /// not present.
+    ///
+    /// If another thread changes the value after `f` has read it, `f` is called again with the new
+    /// value, so `f` may be called several times and should not have side effects.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.update(&1, |v| v + 1), Some(11));
+    /// assert_eq!(map.update(&2, |v| v + 1), None);
+    /// ```
+    pub fn update<Q, F>(&mut self, key: &Q, mut f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> V,
+        V: Clone,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, r| {
+            table
+                .update(r, hash, key, |v| Some(f(v)))
+                .ok()
+                .map(|new| unsafe { (*new).clone() })
+        })
+    }
+
+    /// Atomically replaces the value corresponding to the key with `new`, but only if the current
+    /// value is equal to `expected`.
+    ///
+    /// Returns `Ok(())` if the value was replaced. Otherwise, returns a clone of the current value,
+    /// or `None` if the key is not present.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.compare_exchange(&1, &"a", "b"), Ok(()));
+    /// assert_eq!(map.compare_exchange(&1, &"a", "c"), Err(Some("b")));
+    /// assert_eq!(map.compare_exchange(&2, &"a", "c"), Err(None));
+    /// ```
+    pub fn compare_exchange<Q>(&mut self, key: &Q, expected: &V, new: V) -> Result<(), Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        let hash = self.map.hash(key);
+
+        self.operate(|table, r| {
+            let ret = table.update(r, hash, key, |v| {
+                if v == expected {
+                    Some(new.clone())
+                } else {
+                    None
+                }
+            });
+            match ret {
+                Ok(_) => Ok(()),
+                Err(current) => Err(current.map(|v| unsafe { (*v).clone() })),
+            }
+        })
+    }
+
+    /// Atomically sets the value corresponding to the key to the result of applying `f` to the
+    /// current value, or to `None` if the key is not present, and returns a clone of the new
+    /// value.
+    ///
+    /// If `f` returns `None`, the key is removed from the map. If the key was not present and `f`
+    /// returns `Some`, the key is inserted. As with [`MapHandle::update`], `f` may be called several
+    /// times if other threads modify the key concurrently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(0));
+    /// assert_eq!(map.compute(1, |v| Some(v.map_or(0, |v| v + 1))), Some(1));
+    /// assert_eq!(map.compute(1, |_| None), None);
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+        V: Clone,
+    {
+        let hash = self.map.hash(&key);
+
+        self.operate(|table, r| {
+            table
+                .compute(r, hash, key, f)
+                .map(|v| unsafe { (*v).clone() })
+        })
     }
 
-    /// Removes a key from the map, returning the value at the key if the key was previously in the
-    /// map.
+    /// Removes a key from the map, returning a clone of the value at the key if the key was
+    /// previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
     ///
     /// # Examples
     ///
@@ -259,19 +721,75 @@ where
     /// assert_eq!(map.remove(&1), Some("a"));
     /// assert_eq!(map.remove(&1), None);
     /// ```
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        self.refresh = self.refresh + 1;
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.remove_if(key, |_| true)
+    }
 
-        self.epoch_counter.fetch_add(1, OSC);
-        let ret = self.map.table.delete(key, &mut self.remove_nodes);
-        self.epoch_counter.fetch_add(1, OSC);
+    /// Removes a key from the map if `pred` returns `true` for its value, returning a clone of the
+    /// value if the key was removed.
+    ///
+    /// The check and the removal happen atomically: the key is only removed if its value has not
+    /// changed since `pred` was called. If the value did change, `pred` is called again with the
+    /// new value.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, 10);
+    /// assert_eq!(map.remove_if(&1, |v| *v > 10), None);
+    /// assert_eq!(map.remove_if(&1, |v| *v == 10), Some(10));
+    /// assert_eq!(map.get(&1), None);
+    /// ```
+    pub fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> bool,
+        V: Clone,
+    {
+        let hash = self.map.hash(key);
 
-        if self.refresh == REFRESH_RATE {
-            self.refresh = 0;
-            self.cleanup();
-        }
+        self.operate(|table, r| {
+            table
+                .remove(r, hash, key, pred)
+                .map(|v| unsafe { (*v).clone() })
+        })
+    }
 
-        ret
+    /// Removes a key from the map if its value is equal to `expected`, returning a clone of the
+    /// value if the key was removed.
+    ///
+    /// The key may be any borrowed form of the map's key type, but `Hash` and `Eq` on the borrowed
+    /// form *must* match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove_if_eq(&1, &"b"), None);
+    /// assert_eq!(map.remove_if_eq(&1, &"a"), Some("a"));
+    /// ```
+    pub fn remove_if_eq<Q>(&mut self, key: &Q, expected: &V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        self.remove_if(key, |v| v == expected)
     }
 
     /// Returns the number of elements in the map.
@@ -287,7 +805,7 @@ where
     /// assert_eq!(a.len(), 1);
     /// ```
     pub fn len(&self) -> usize {
-        self.map.table.nitems.load(OSC)
+        self.map.table.len()
     }
 
     /// Returns true if the map contains no elements.
@@ -303,59 +821,482 @@ where
     /// assert!(!a.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.map.table.nitems.load(OSC) == 0
+        self.map.table.len() == 0
     }
 }
 
-impl<K, V> Clone for MapHandle<K, V> {
-    fn clone(&self) -> Self {
-        let ret = Self {
-            map: Arc::clone(&self.map),
-            epoch_counter: Arc::new(AtomicUsize::new(0)),
-            remove_nodes: Vec::new(),
-            remove_val: Vec::new(),
-            refresh: 0,
-        };
-
-        let mut handles_vec = self.map.handles.write().unwrap(); //handles vector
-        handles_vec.push(Arc::clone(&ret.epoch_counter));
-
-        ret
+impl<K, V, S> MapHandle<K, V, S> {
+    /// An iterator visiting all key-value pairs in the map, yielding clones of each key and
+    /// value.
+    ///
+    /// The iterator is weakly consistent: every key that is in the map for the entire iteration is
+    /// yielded exactly once, while keys that are inserted or removed concurrently may or may not
+    /// be yielded. Keys are yielded in no particular order.
+    ///
+    /// This handle stays in a critical section until the iterator is dropped, which delays memory
+    /// reclamation for all handles to the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut entries: Vec<_> = map.iter().collect();
+    /// entries.sort();
+    /// assert_eq!(entries, vec![(1, "a"), (2, "b")]);
+    /// ```
+    pub fn iter(&mut self) -> Iter<'_, K, V, S> {
+        Iter {
+            cursor: Cursor::new(self),
+        }
     }
-}
 
-/// A shared, concurrent hash map.
-///
-/// See [`MapHandle`] for how to interact with this map.
-pub struct Map<K, V> {
-    table: Table<K, V>,
-    handles: RwLock<Vec<Arc<AtomicUsize>>>, //(started, finished)
-}
-
-impl<K, V> Map<K, V> {
-    /// Create a new, shared map and return a handle to it.
+    /// An iterator visiting all keys in the map, yielding clones of each key.
     ///
-    /// The map will have `nbuckets` buckets to distribute stored keys among. If there are many
-    /// more keys than buckets, performance will suffer, as all the keys in a key's bucket must be
-    /// searched to read or update that key.
-    pub fn with_capacity(nbuckets: usize) -> MapHandle<K, V> {
-        let new_hashmap = Map {
-            table: Table::new(nbuckets),
-            handles: RwLock::new(Vec::new()),
-        };
-        let ret = MapHandle {
-            map: Arc::new(new_hashmap),
-            epoch_counter: Arc::new(AtomicUsize::new(0)),
-            remove_nodes: Vec::new(),
-            remove_val: Vec::new(),
-            refresh: 0,
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut keys: Vec<_> = map.keys().collect();
+    /// keys.sort();
+    /// assert_eq!(keys, vec![1, 2]);
+    /// ```
+    pub fn keys(&mut self) -> Keys<'_, K, V, S> {
+        Keys {
+            cursor: Cursor::new(self),
+        }
+    }
+
+    /// An iterator visiting all values in the map, yielding clones of each value.
+    ///
+    /// See [`MapHandle::iter`] for the consistency guarantees of the iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// let mut values: Vec<_> = map.values().collect();
+    /// values.sort();
+    /// assert_eq!(values, vec!["a", "b"]);
+    /// ```
+    pub fn values(&mut self) -> Values<'_, K, V, S> {
+        Values {
+            cursor: Cursor::new(self),
+        }
+    }
+}
+
+impl<K, V, S> MapHandle<K, V, S> {
+    /// Declares that this handle is not in the middle of an operation, and reclaims the garbage it
+    /// has retired right away, instead of after its next few operations.
+    ///
+    /// Garbage that another thread may still be reading is left for a later reclamation, so this
+    /// never waits for other threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, String::from("a"));
+    /// map.remove(&1);
+    /// // the removed string is freed now, and not 1000 operations later
+    /// map.quiesce();
+    /// ```
+    pub fn quiesce(&mut self) {
+        self.refresh = 0;
+        self.cleanup();
+    }
+
+    /// Reclaims all of the garbage this handle has retired, waiting for any thread that may still
+    /// be reading some of it to finish the operation it is in.
+    ///
+    /// Unlike [`MapHandle::quiesce`], this blocks until every thread that is currently in the
+    /// middle of an operation on the map has finished that operation. If the map was built with
+    /// [`Builder::reclaim_in_background`], the garbage is passed on to the reclaimer thread instead,
+    /// and this returns without waiting for it to be freed.
+    pub fn flush(&mut self) {
+        self.quiesce();
+        if self.limbo.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
+        while !self.limbo.is_empty() {
+            thread::yield_now();
+            reclaim(&mut self.limbo);
+        }
+        let waited = start.elapsed().as_nanos() as u64;
+        self.map.wait_nanos.fetch_add(waited, OSC);
+    }
+
+    /// Returns statistics about the memory this handle and the map are holding on to.
+    ///
+    /// The retired nodes and values are those retired by this handle that have not been freed
+    /// yet, including those held up by other threads' critical sections. Once they have been
+    /// handed to the reclaimer thread of a map built with [`Builder::reclaim_in_background`],
+    /// they are no longer counted. Handles that are [offline](MapHandle::offline) do not count
+    /// towards `handles`. `cleanups` and `wait_time` are totals over all handles, where only
+    /// [`MapHandle::flush`] ever waits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.remove(&1);
+    /// assert_eq!(map.stats().retired_values, 1);
+    ///
+    /// map.flush();
+    /// let stats = map.stats();
+    /// assert_eq!(stats.retired_values, 0);
+    /// assert_eq!(stats.handles, 1);
+    /// assert_eq!(stats.cleanups, 1);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        let limbo = |count: fn(&Batch<K, V>) -> usize| self.limbo.iter().map(count).sum::<usize>();
+        Stats {
+            retired_nodes: self.retired.nodes.len() + limbo(|b| b.nodes.len()),
+            retired_values: self.retired.vals.len() + limbo(|b| b.vals.len()),
+            handles: self.map.handles.read().unwrap().len(),
+            cleanups: self.map.cleanups.load(OSC),
+            wait_time: Duration::from_nanos(self.map.wait_nanos.load(OSC)),
+            live_bytes: self.map.table.live_bytes(),
+        }
+    }
+
+    /// Walks the whole map, and reports how its keys are spread over its buckets.
+    ///
+    /// This visits every node in the map, and this handle stays in a critical section while it
+    /// does, which delays memory reclamation for all handles to the map. The report is not a
+    /// consistent snapshot: keys that are inserted or removed during the walk may or may not be
+    /// counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// for i in 0..10 {
+    ///     map.insert(i, i);
+    /// }
+    ///
+    /// let report = map.load_report();
+    /// assert_eq!(report.buckets, 16);
+    /// assert_eq!(report.histogram.iter().sum::<usize>(), 16);
+    /// assert_eq!(report.deleted_nodes, 0);
+    /// ```
+    pub fn load_report(&mut self) -> LoadReport {
+        self.operate(|table, r| table.load_report(r))
+    }
+
+    /// Takes this handle out of the map's reclamation scheme, for a thread that is about to go
+    /// idle.
+    ///
+    /// The handle frees what it can of its garbage, and leaves the rest to the other handles to
+    /// free. While it is offline, other handles do not keep track of it at all. The handle comes
+    /// back online with [`MapHandle::online`], or automatically with its next operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    ///
+    /// let mut map = Map::with_capacity(16);
+    /// map.insert(1, "a");
+    /// map.offline();
+    /// // ... wait for more work ...
+    /// map.online();
+    /// assert_eq!(map.get(&1), Some("a"));
+    /// ```
+    pub fn offline(&mut self) {
+        if self.offline {
+            return;
+        }
+
+        // free what we can of the garbage this handle has retired, and leave the rest to the
+        // handles that remain
+        self.quiesce();
+        if !self.limbo.is_empty() {
+            let mut orphans = self.map.orphans.lock().unwrap();
+            orphans.append(&mut self.limbo);
+        }
+
+        // deregister, so that future cleanups do not scan this handle's counter anymore
+        let mut handles_vec = self.map.handles.write().unwrap();
+        handles_vec.retain(|h| !Arc::ptr_eq(h, &self.epoch_counter));
+        self.offline = true;
+    }
+
+    /// Brings a handle that was taken offline with [`MapHandle::offline`] back into the map's
+    /// reclamation scheme.
+    ///
+    /// Calling this is optional, since the next operation on the handle does the same, but it
+    /// moves the cost of doing so out of that operation.
+    pub fn online(&mut self) {
+        if !self.offline {
+            return;
+        }
+
+        let mut handles_vec = self.map.handles.write().unwrap();
+        handles_vec.push(Arc::clone(&self.epoch_counter));
+        self.offline = false;
+    }
+}
+
+/// Walks the map's table inside a critical section of the handle it borrows.
+struct Cursor<'a, K: 'a, V: 'a, S: 'a> {
+    handle: &'a mut MapHandle<K, V, S>,
+    cursor: TableCursor<K, V>,
+}
+
+impl<'a, K, V, S> Cursor<'a, K, V, S> {
+    fn new(handle: &'a mut MapHandle<K, V, S>) -> Self {
+        handle.enter();
+        let cursor = TableCursor::new(&handle.map.table);
+        Cursor { handle, cursor }
+    }
+
+    /// Advances to the next entry, and calls `then` with its key and value.
+    fn next_and<F, R>(&mut self, then: F) -> Option<R>
+    where
+        F: FnOnce(&K, &V) -> R,
+    {
+        let handle = &mut *self.handle;
+        let mut local = Local {
+            epoch: &handle.epoch_counter,
+            retired: &mut handle.retired,
         };
+        // the handle has been in the same critical section since the cursor was created
+        let (k, v) = unsafe { self.cursor.next(&handle.map.table, &mut local)? };
+        Some(then(k, v))
+    }
+}
 
-        //push the first maphandle into the epoch system
-        let hashmap = Arc::clone(&ret.map);
-        let mut handles_vec = hashmap.handles.write().unwrap();
-        handles_vec.push(Arc::clone(&ret.epoch_counter));
-        ret
+impl<'a, K, V, S> Drop for Cursor<'a, K, V, S> {
+    fn drop(&mut self) {
+        self.handle.exit();
+    }
+}
+
+/// An iterator over the entries of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::iter`]. See its documentation for more.
+pub struct Iter<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|k, v| (k.clone(), v.clone()))
+    }
+}
+
+/// An iterator over the keys of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::keys`]. See its documentation for more.
+pub struct Keys<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Keys<'a, K, V, S>
+where
+    K: Clone,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|k, _| k.clone())
+    }
+}
+
+/// An iterator over the values of a [`Map`].
+///
+/// This `struct` is created by [`MapHandle::values`]. See its documentation for more.
+pub struct Values<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    cursor: Cursor<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Values<'a, K, V, S>
+where
+    V: Clone,
+{
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_and(|_, v| v.clone())
+    }
+}
+
+impl<K, V, S> ConcurrentMap<K, V> for MapHandle<K, V, S>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    type Iter<'a>
+        = Iter<'a, K, V, S>
+    where
+        Self: 'a;
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        MapHandle::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.get_cloned(key)
+    }
+
+    fn get_or_insert_with<F>(&mut self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        MapHandle::get_or_insert_with(self, key, make)
+    }
+
+    fn update<F>(&mut self, key: &K, f: F) -> Option<V>
+    where
+        F: FnMut(&V) -> V,
+    {
+        MapHandle::update(self, key, f)
+    }
+
+    fn compute<F>(&mut self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        MapHandle::compute(self, key, f)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        MapHandle::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        MapHandle::len(self)
+    }
+
+    fn iter(&mut self) -> Self::Iter<'_> {
+        MapHandle::iter(self)
+    }
+}
+
+impl<K, V, S> Clone for MapHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        Self::register(Arc::clone(&self.map))
+    }
+}
+
+impl<K, V, S> Drop for MapHandle<K, V, S> {
+    fn drop(&mut self) {
+        if self.epoch_counter.load(OSC) % 2 == 1 {
+            // an iterator over this handle was leaked with `mem::forget`, so its critical section
+            // was never left. nothing can reach what the iterator loaded anymore.
+            self.epoch_counter.fetch_add(1, OSC);
+        }
+
+        self.offline();
+    }
+}
+
+/// A shared, concurrent hash map.
+///
+/// See [`MapHandle`] for how to interact with this map.
+pub struct Map<K, V, S = RandomState> {
+    table: Table<K, V>,
+    hash_builder: S,
+    handles: Arc<Registry>,
+    /// Garbage of dropped handles that could not be freed yet.
+    orphans: Mutex<Vec<Batch<K, V>>>,
+    reclaim: Reclaim,
+    reclaimer: Option<Reclaimer<K, V>>,
+    /// The number of cleanups the handles have run.
+    cleanups: AtomicUsize,
+    /// The time the handles have spent in [`MapHandle::flush`] waiting for other threads.
+    wait_nanos: AtomicU64,
+}
+
+impl<K, V> Map<K, V, RandomState> {
+    /// Create a new, shared map and return a handle to it.
+    ///
+    /// The map will initially have `nbuckets` buckets (rounded up to a power of two) to distribute
+    /// stored keys among. The number of buckets doubles whenever the map holds more than two keys
+    /// per bucket on average, so `nbuckets` only needs to be a rough estimate.
+    ///
+    /// Keys are hashed with a [`RandomState`] that is seeded differently for every map, which
+    /// makes it hard for an attacker to pick keys that all end up in the same bucket.
+    pub fn with_capacity(nbuckets: usize) -> MapHandle<K, V> {
+        Self::with_capacity_and_hasher(nbuckets, RandomState::new())
+    }
+
+    /// Returns a builder for a map with a non-default configuration.
+    ///
+    /// See [`Builder`] for the available options.
+    pub fn builder() -> Builder<K, V> {
+        Builder::new()
+    }
+}
+
+impl<K, V, S> Map<K, V, S> {
+    /// Create a new, shared map that uses `hash_builder` to hash keys, and return a handle to it.
+    ///
+    /// See [`Map::with_capacity`] for the meaning of `nbuckets`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::Map;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    ///
+    /// let hasher = BuildHasherDefault::<DefaultHasher>::default();
+    /// let mut map = Map::with_capacity_and_hasher(16, hasher);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some("a"));
+    /// ```
+    pub fn with_capacity_and_hasher(nbuckets: usize, hash_builder: S) -> MapHandle<K, V, S> {
+        Builder::new()
+            .capacity(nbuckets)
+            .hasher(hash_builder)
+            .build()
+    }
+}
+
+impl<K, V, S> Map<K, V, S>
+where
+    S: BuildHasher,
+{
+    fn hash<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Hash,
+    {
+        table::hash(&self.hash_builder, key)
     }
 }
 
@@ -363,7 +1304,14 @@ impl<K, V> Map<K, V> {
 mod tests {
     use super::*;
     use rand::{thread_rng, Rng};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{BuildHasherDefault, Hasher};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::mpsc;
     use std::thread;
+    use std::time;
+    use table::MAX_LOAD_FACTOR;
+    use test_util::{ConstantHasher, Tracked, Tracker};
 
     /*
     the data produced is a bit strange because of the way I take mod to test only even values
@@ -389,9 +1337,8 @@ mod tests {
                     if two % 3 == 0 {
                         new_handle.insert(val, val);
                     } else if two % 3 == 1 {
-                        let v = new_handle.get(&val);
-                        if v.is_some() {
-                            assert_eq!(v.unwrap(), val);
+                        if let Some(v) = new_handle.get(&val) {
+                            assert_eq!(v, val);
                         }
                     } else {
                         new_handle.remove(&val);
@@ -451,7 +1398,7 @@ mod tests {
         assert_eq!(new_hashmap.insert(5, 5), None);
 
         let cln = Arc::clone(&new_hashmap.map);
-        assert_eq!(cln.table.nitems.load(OSC), 9);
+        assert_eq!(cln.table.len(), 9);
 
         new_hashmap.insert(3, 8); //repeated
 
@@ -469,6 +1416,603 @@ mod tests {
         assert!(new_hashmap.get(&3).unwrap() != 2); // test that it changed
     }
 
+    #[test]
+    fn hashmap_grows() {
+        let mut handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            assert_eq!(handle.insert(i, i), None);
+        }
+
+        let nbuckets = handle.map.table.nbuckets();
+        assert!(nbuckets >= 1000 / MAX_LOAD_FACTOR);
+        assert_eq!(handle.len(), 1000);
+
+        for i in 0..1000 {
+            assert_eq!(handle.get(&i), Some(i));
+        }
+        for i in (0..1000).filter(|i| i % 2 == 0) {
+            assert_eq!(handle.remove(&i), Some(i));
+        }
+        for i in 0..1000 {
+            assert_eq!(handle.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+    }
+
+    #[test]
+    fn hashmap_grows_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let per_thread = 10000;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in (t * per_thread)..((t + 1) * per_thread) {
+                        assert_eq!(new_handle.insert(i, i), None);
+                        assert_eq!(new_handle.get(&i), Some(i));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let mut handle = handle;
+        assert_eq!(handle.len(), nthreads * per_thread);
+        for i in 0..(nthreads * per_thread) {
+            assert_eq!(handle.get(&i), Some(i));
+        }
+    }
+
+    #[test]
+    fn hashmap_non_copy() {
+        let mut handle = Map::with_capacity(8);
+        assert_eq!(handle.insert(1, String::from("a")), None);
+        assert_eq!(handle.insert(1, String::from("b")), Some(String::from("a")));
+        assert_eq!(handle.get_cloned(&1), Some(String::from("b")));
+        assert_eq!(handle.get_and(&1, |v| v.len()), Some(1));
+        assert_eq!(handle.remove(&1), Some(String::from("b")));
+        assert_eq!(handle.get_and(&1, |v| v.len()), None);
+    }
+
+    #[test]
+    fn hashmap_drops_replaced_values() {
+        let tracker = Arc::new(());
+        let mut handle = Map::with_capacity(8);
+        for _ in 0..(2 * REFRESH_RATE) {
+            handle.insert(0, Arc::clone(&tracker));
+        }
+
+        // the last operation triggered a cleanup, which dropped every value but the current one
+        assert_eq!(Arc::strong_count(&tracker), 2);
+    }
+
+    #[test]
+    fn hashmap_stalled_reader() {
+        let mut handle = Map::with_capacity(16);
+        handle.insert(0, 0);
+
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let mut reader = handle.clone();
+        let t = thread::spawn(move || {
+            reader.get_and(&0, |_| {
+                entered_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            });
+        });
+        entered_rx.recv().unwrap();
+
+        // cleanups go ahead without waiting for the reader, but cannot free what it may be reading
+        for i in 1..=2 * REFRESH_RATE {
+            handle.insert(i, i);
+            handle.remove(&i);
+        }
+        assert!(!handle.limbo.is_empty());
+
+        release_tx.send(()).unwrap();
+        t.join().unwrap();
+        for _ in 0..REFRESH_RATE {
+            handle.get(&0);
+        }
+        assert!(handle.limbo.is_empty());
+    }
+
+    #[test]
+    fn hashmap_reclaim_limits() {
+        let pending =
+            |h: &MapHandle<usize, [u8; 256]>| h.retired.nodes.len() + h.retired.vals.len();
+
+        // read-heavy: never clean up based on the number of operations
+        let mut handle = Map::builder().reclaim_every(usize::MAX).build();
+        for i in 0..2 * REFRESH_RATE {
+            handle.insert(i, [0; 256]);
+            handle.remove(&i);
+        }
+        assert_eq!(handle.retired.vals.len(), 2 * REFRESH_RATE);
+
+        let mut handle = Map::builder()
+            .reclaim_every(usize::MAX)
+            .max_pending(10)
+            .build();
+        for i in 0..100 {
+            handle.insert(i, [0; 256]);
+            handle.remove(&i);
+            assert!(pending(&handle) < 10);
+        }
+
+        let mut handle = Map::builder()
+            .reclaim_every(usize::MAX)
+            .max_pending_bytes(1024)
+            .build();
+        for i in 0..100 {
+            handle.insert(i, [0; 256]);
+            handle.remove(&i);
+            assert!(handle.retired.vals.len() < 4);
+        }
+        assert!(handle.limbo.is_empty());
+    }
+
+    #[test]
+    fn hashmap_quiesce_flush() {
+        let mut handle = Map::with_capacity(16);
+        for i in 0..10 {
+            handle.insert(i, i);
+            handle.remove(&i);
+        }
+        assert_eq!(handle.retired.vals.len(), 10);
+        handle.quiesce();
+        assert!(handle.retired.vals.is_empty() && handle.limbo.is_empty());
+
+        // a reader in the middle of an operation holds up the garbage until it is done
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let mut reader = handle.clone();
+        reader.insert(100, 100);
+        let t = thread::spawn(move || {
+            reader.get_and(&100, |_| {
+                entered_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            });
+        });
+        entered_rx.recv().unwrap();
+
+        handle.insert(1, 1);
+        handle.remove(&1);
+        handle.quiesce();
+        assert_eq!(handle.limbo.len(), 1);
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(50));
+            release_tx.send(()).unwrap();
+        });
+        handle.flush();
+        assert!(handle.limbo.is_empty());
+        assert!(handle.stats().wait_time > time::Duration::from_secs(0));
+        t.join().unwrap();
+        releaser.join().unwrap();
+    }
+
+    #[test]
+    fn hashmap_stats() {
+        let mut handle: MapHandle<usize, usize> = Map::with_capacity(16);
+        let other = handle.clone();
+        for i in 0..10 {
+            handle.insert(i, i);
+        }
+        for i in 0..5 {
+            handle.remove(&i);
+        }
+
+        let stats = handle.stats();
+        assert_eq!(stats.retired_nodes, 5);
+        assert_eq!(stats.retired_values, 5);
+        assert_eq!(stats.handles, 2);
+        assert_eq!(stats.cleanups, 0);
+        let entry = mem::size_of::<Node<usize, usize>>() + mem::size_of::<usize>();
+        assert_eq!(stats.live_bytes, 5 * entry);
+
+        // nobody else is in a critical section, so the garbage is freed right away
+        handle.quiesce();
+        let stats = handle.stats();
+        assert_eq!((stats.retired_nodes, stats.retired_values), (0, 0));
+        assert_eq!(stats.cleanups, 1);
+
+        drop(other);
+        assert_eq!(handle.stats().handles, 1);
+    }
+
+    #[test]
+    fn hashmap_offline() {
+        let mut handle = Map::with_capacity(16);
+        let mut other = handle.clone();
+        assert_eq!(handle.map.handles.read().unwrap().len(), 2);
+
+        other.insert(1, 1);
+        other.remove(&1);
+        other.offline();
+        assert!(other.retired.vals.is_empty() && other.limbo.is_empty());
+        assert_eq!(handle.map.handles.read().unwrap().len(), 1);
+
+        // going offline twice, or dropping an offline handle, deregisters only once
+        other.offline();
+        assert_eq!(handle.map.handles.read().unwrap().len(), 1);
+
+        other.online();
+        assert_eq!(handle.map.handles.read().unwrap().len(), 2);
+        other.offline();
+
+        // an operation brings the handle back online
+        assert_eq!(other.get(&1), None);
+        assert_eq!(handle.map.handles.read().unwrap().len(), 2);
+        other.offline();
+        drop(other);
+        assert_eq!(handle.map.handles.read().unwrap().len(), 1);
+        handle.insert(2, 2);
+    }
+
+    #[test]
+    fn hashmap_panic() {
+        let mut handle = Map::with_capacity(16);
+        handle.insert(0, 0);
+
+        let caught = panic::catch_unwind(AssertUnwindSafe(|| {
+            handle.get_and(&0, |_| panic!("oops"));
+        }));
+        assert!(caught.is_err());
+        assert_eq!(handle.epoch_counter.load(OSC) % 2, 0);
+
+        // the handle that caught the panic does not hold up its own garbage, or anyone else's
+        let mut other = handle.clone();
+        handle.remove(&0);
+        handle.quiesce();
+        assert_eq!(handle.stats().retired_values, 0);
+        other.insert(1, 1);
+        other.remove(&1);
+        other.quiesce();
+        assert_eq!(other.stats().retired_values, 0);
+    }
+
+    #[test]
+    fn hashmap_iter() {
+        let mut handle = Map::with_capacity(2);
+        for i in 0..100 {
+            handle.insert(i, i * 2);
+        }
+        for i in (0..100).filter(|i| i % 3 == 0) {
+            handle.remove(&i);
+        }
+
+        let mut entries: Vec<_> = handle.iter().collect();
+        entries.sort();
+        let expected: Vec<_> = (0..100)
+            .filter(|i| i % 3 != 0)
+            .map(|i| (i, i * 2))
+            .collect();
+        assert_eq!(entries, expected);
+
+        assert_eq!(handle.keys().count(), handle.len());
+        assert_eq!(
+            handle.values().sum::<i32>(),
+            expected.iter().map(|e| e.1).sum()
+        );
+        assert_eq!(handle.epoch_counter.load(OSC) % 2, 0);
+    }
+
+    #[test]
+    fn hashmap_iter_concurr() {
+        // keys that stay in the map must be seen exactly once, even while other keys come and go
+        let mut handle = Map::with_capacity(1);
+        for i in 0..1000 {
+            handle.insert(i, i);
+        }
+
+        let mut writer = handle.clone();
+        let t = thread::spawn(move || {
+            for i in 1000..20000 {
+                writer.insert(i, i);
+                if i % 2 == 0 {
+                    writer.remove(&i);
+                }
+            }
+        });
+
+        for _ in 0..10 {
+            let mut keys: Vec<_> = handle.keys().filter(|&k| k < 1000).collect();
+            keys.sort();
+            assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn hashmap_insert_race() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    let mut won = 0;
+                    let mut seen = Vec::new();
+                    for i in 0..1000 {
+                        if new_handle.try_insert(i, t).is_ok() {
+                            won += 1;
+                        }
+                        seen.push(new_handle.get_or_insert_with(i + 1000, || t));
+                    }
+                    (won, seen)
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        // every key was inserted by exactly one thread, and everyone saw the winner's value
+        let mut handle = handle;
+        assert_eq!(results.iter().map(|r| r.0).sum::<usize>(), 1000);
+        for (i, winner) in (0..1000).map(|i| (i, handle.get(&(i + 1000)).unwrap())) {
+            for r in &results {
+                assert_eq!(r.1[i], winner);
+            }
+        }
+        assert_eq!(handle.len(), 2000);
+    }
+
+    #[test]
+    fn hashmap_custom_hasher() {
+        let mut handle =
+            Map::with_capacity_and_hasher(4, BuildHasherDefault::<ConstantHasher>::default());
+        for i in 0..100 {
+            assert_eq!(handle.insert(i, i), None);
+        }
+        for i in (0..100).step_by(2) {
+            assert_eq!(handle.remove(&i), Some(i));
+        }
+        for i in 0..100 {
+            assert_eq!(handle.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+        assert_eq!(handle.len(), 50);
+    }
+
+    #[test]
+    fn hashmap_load_report() {
+        // every key collides, so they all end up in the same bucket
+        let mut handle =
+            Map::with_capacity_and_hasher(4, BuildHasherDefault::<ConstantHasher>::default());
+        for i in 0..100 {
+            handle.insert(i, i);
+        }
+        for i in (0..100).step_by(2) {
+            handle.remove(&i);
+        }
+
+        let report = handle.load_report();
+        assert_eq!(report.buckets, handle.map.table.nbuckets());
+        assert_eq!(report.longest_chain, 50);
+        assert_eq!(report.histogram.len(), 51);
+        assert_eq!(report.histogram[50], 1);
+        assert_eq!(report.empty_buckets, report.buckets - 1);
+        assert_eq!(report.histogram.iter().sum::<usize>(), report.buckets);
+        assert_eq!(report.deleted_nodes, 0);
+    }
+
+    #[test]
+    fn hashmap_collision_resistant() {
+        // keys that all land in the same bucket under an unseeded hasher
+        let nbuckets = 256;
+        let keys: Vec<u64> = (0..)
+            .filter(|k| {
+                let mut hasher = DefaultHasher::new();
+                k.hash(&mut hasher);
+                hasher.finish() as usize & (nbuckets - 1) == 0
+            })
+            .take(nbuckets)
+            .collect();
+
+        let mut handle = Map::with_capacity(nbuckets);
+        for &k in &keys {
+            handle.insert(k, k);
+        }
+        for &k in &keys {
+            assert_eq!(handle.get(&k), Some(k));
+        }
+
+        // the map's own seed spreads them out again
+        let map = &handle.map;
+        assert_eq!(map.table.nbuckets(), nbuckets);
+        let mut load = vec![0; nbuckets];
+        for k in &keys {
+            load[map.hash(k) & (nbuckets - 1)] += 1;
+        }
+        assert!(*load.iter().max().unwrap() <= 10, "{:?}", load);
+
+        // and every map is seeded differently
+        let other = Map::<u64, u64>::with_capacity(nbuckets);
+        assert!(keys.iter().any(|k| map.hash(k) != other.map.hash(k)));
+    }
+
+    #[test]
+    fn hashmap_borrowed_keys() {
+        let mut handle = Map::with_capacity(4);
+        for i in 0..100 {
+            handle.insert(i.to_string(), i);
+        }
+
+        // look up `String` keys by `&str`, without allocating
+        for i in 0..100 {
+            let key = i.to_string();
+            assert!(handle.contains_key(key.as_str()));
+            assert_eq!(handle.get(key.as_str()), Some(i));
+        }
+        assert!(!handle.contains_key("100"));
+        assert_eq!(handle.update("1", |v| v + 1), Some(2));
+        assert_eq!(handle.remove("1"), Some(2));
+        assert!(!handle.contains_key("1"));
+        assert_eq!(handle.len(), 99);
+    }
+
+    #[test]
+    fn hashmap_drop_frees_everything() {
+        let tracker = Arc::new(Tracker::default());
+        let handle = Map::with_capacity(2);
+        let nthreads = 4;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                let tracker = Arc::clone(&tracker);
+                thread::spawn(move || {
+                    let key = |i| (t * 200 + i, Tracked::new(&tracker));
+                    for i in 0..200 {
+                        new_handle.insert(key(i), Tracked::new(&tracker));
+                    }
+                    for i in 0..200 {
+                        match i % 4 {
+                            0 => drop(new_handle.remove(&key(i))),
+                            1 => drop(new_handle.insert(key(i), Tracked::new(&tracker))),
+                            2 => drop(new_handle.try_insert(key(i), Tracked::new(&tracker))),
+                            _ => drop(new_handle.compute(key(i), |v| v.cloned())),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(handle.len(), nthreads * 150);
+
+        // every key and value, whether still in the map or retired, is dropped exactly once
+        drop(handle);
+        assert_eq!(tracker.live(), 0);
+    }
+
+    #[test]
+    fn hashmap_background_reclaimer() {
+        let tracker = Arc::new(Tracker::default());
+        let handle = Map::builder()
+            .reclaim_in_background()
+            .reclaim_every(10)
+            .build();
+        let nthreads = 4;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                let tracker = Arc::clone(&tracker);
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        new_handle.insert(t * 200 + i, Tracked::new(&tracker));
+                        new_handle.insert(t * 200 + i, Tracked::new(&tracker));
+                        if i % 2 == 0 {
+                            new_handle.remove(&(t * 200 + i));
+                        }
+                        // nothing is ever freed inline
+                        assert!(new_handle.limbo.is_empty());
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(handle.len(), nthreads * 100);
+
+        // the reclaimer frees the replaced and removed values without any help
+        let live = || tracker.live();
+        let start = time::Instant::now();
+        while live() > nthreads * 100 && start.elapsed() < time::Duration::from_secs(10) {
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        assert_eq!(live(), nthreads * 100);
+
+        // dropping the map stops the reclaimer, and everything is freed by the time drop returns
+        drop(handle);
+        assert_eq!(live(), 0);
+    }
+
+    #[test]
+    fn hashmap_update_concurr() {
+        let handle = Map::with_capacity(1);
+        let nthreads = 8;
+        let nkeys = 64;
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = i % nkeys;
+                        // the first increment of each key inserts it
+                        new_handle.compute(key, |v| Some(v.map_or(1, |v| v + 1)));
+                        new_handle.compute(key + nkeys, |v| Some(v.map_or(1, |v| v + 1)));
+                        new_handle.update(&(key + nkeys), |v| v + 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        // no increment was lost
+        let mut handle = handle;
+        let total: usize = (0..nkeys).map(|i| handle.get(&i).unwrap()).sum();
+        assert_eq!(total, nthreads * 1000);
+        let total: usize = (nkeys..2 * nkeys).map(|i| handle.get(&i).unwrap()).sum();
+        assert_eq!(total, 2 * nthreads * 1000);
+        assert_eq!(handle.len(), 2 * nkeys);
+
+        // removing through compute is reflected in the length
+        for i in 0..nkeys {
+            assert_eq!(handle.compute(i, |_| None), None);
+        }
+        assert_eq!(handle.len(), nkeys);
+        assert_eq!(handle.update(&0, |v| v + 1), None);
+    }
+
+    #[test]
+    fn hashmap_compare_exchange_concurr() {
+        let mut handle = Map::with_capacity(1);
+        let nthreads = 8;
+        handle.insert(0, 0);
+
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                let mut new_handle = handle.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        // increment a counter with a read-modify-CAS loop
+                        let mut cur = new_handle.get(&0).unwrap();
+                        while let Err(actual) = new_handle.compare_exchange(&0, &cur, cur + 1) {
+                            cur = actual.unwrap();
+                        }
+
+                        // take a lease, and release it only if we still hold it
+                        let key = 1 + i % 16;
+                        if new_handle.try_insert(key, t).is_ok() {
+                            assert!(new_handle.remove_if_eq(&key, &t).is_some());
+                        } else {
+                            assert!(new_handle.remove_if_eq(&key, &nthreads).is_none());
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(handle.get(&0), Some(nthreads * 1000));
+        assert_eq!(handle.len(), 1);
+        assert!(handle.remove_if(&0, |v| *v == 0).is_none());
+        assert!(handle.remove_if(&0, |v| *v == nthreads * 1000).is_some());
+        assert!(handle.is_empty());
+    }
+
     // /**
     //  * Added Test Case from https://gitlab.nebulanet.cc/xacrimon/rs-hm-bench
     //  */
@@ -0,0 +1,84 @@
+use super::{in_critical_section, reclaim, Batch, Registry};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long the reclaimer waits for new garbage before it checks its pending batches again.
+const RECLAIM_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A thread that frees the garbage retired by the handles to a map, so that they don't have to.
+///
+/// Handles send the garbage they retire over `queue`. The thread records which handles were in a
+/// critical section when it received the garbage, and frees it once they have all left theirs.
+/// Dropping the `Reclaimer` stops the thread, which frees everything it still holds before
+/// exiting.
+pub(super) struct Reclaimer<K, V> {
+    queue: Option<Sender<Batch<K, V>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<K, V> Reclaimer<K, V>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    /// Starts a reclaimer for the map whose handles are registered in `handles`.
+    pub(super) fn spawn(handles: Arc<Registry>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(String::from("concache-reclaimer"))
+            .spawn(move || run(&handles, &rx))
+            .expect("failed to spawn reclaimer thread");
+
+        Reclaimer {
+            queue: Some(tx),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<K, V> Reclaimer<K, V> {
+    /// Hands `garbage` to the reclaimer thread, or gives it back if the thread is gone.
+    pub(super) fn retire(&self, garbage: Batch<K, V>) -> Result<(), Batch<K, V>> {
+        let queue = self.queue.as_ref().unwrap();
+        queue.send(garbage).map_err(|e| e.0)
+    }
+}
+
+impl<K, V> Drop for Reclaimer<K, V> {
+    fn drop(&mut self) {
+        // hanging up tells the thread to finish
+        drop(self.queue.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<K, V>(handles: &Registry, queue: &Receiver<Batch<K, V>>) {
+    let mut limbo = Vec::new();
+    loop {
+        let received = if limbo.is_empty() {
+            queue.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            queue.recv_timeout(RECLAIM_INTERVAL)
+        };
+
+        match received {
+            Ok(mut garbage) => {
+                // the garbage was retired before we got it, so any thread that may be reading it
+                // is still in a critical section now
+                garbage.pending = in_critical_section(handles);
+                limbo.push(garbage);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        reclaim(&mut limbo);
+    }
+
+    // the queue only hangs up once the map is dropped, which means that there are no handles left
+    // that could be reading any of the garbage
+    drop(limbo);
+}
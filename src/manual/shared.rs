@@ -0,0 +1,452 @@
+use super::{Map, MapHandle};
+use std::borrow::Borrow;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use thread_local::ThreadLocal;
+
+/// The handles a thread uses to access a [`SharedMap`].
+struct Local<K, V, S> {
+    /// How many operations on the map this thread is currently inside of.
+    depth: Cell<usize>,
+    /// Handles that are not currently in use; there is more than one only if an operation was
+    /// started from within the closure of another.
+    idle: RefCell<Vec<MapHandle<K, V, S>>>,
+}
+
+/// A [`Map`] that can be shared between threads by reference.
+///
+/// Every operation on a [`MapHandle`] needs `&mut self`, since the handle tracks what its thread
+/// is doing for the map's memory reclamation. A `SharedMap` instead keeps one `MapHandle` per
+/// thread, which is registered the first time a thread uses the map, so all of its operations
+/// take `&self` and the map can be put in an `Arc` like any other `Sync` type.
+///
+/// Looking up the current thread's handle has a small cost on every operation. Threads that do a
+/// lot of work on the map can get a handle of their own with [`SharedMap::handle`].
+///
+/// The handle of a thread that exits stays registered until another thread reuses its slot, or
+/// until the `SharedMap` is dropped. Since it is not in a critical section, it does not hold up
+/// reclamation by other handles.
+///
+/// # Examples
+///
+/// ```
+/// use concache::manual::SharedMap;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let map = Arc::new(SharedMap::with_capacity(16));
+/// let threads: Vec<_> = (0..4)
+///     .map(|i| {
+///         let map = Arc::clone(&map);
+///         thread::spawn(move || map.insert(i, i * 10))
+///     })
+///     .collect();
+/// for t in threads {
+///     t.join().unwrap();
+/// }
+/// assert_eq!(map.len(), 4);
+/// assert_eq!(map.get(&2), Some(20));
+/// ```
+pub struct SharedMap<K, V, S = RandomState>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send + Sync,
+{
+    map: Arc<Map<K, V, S>>,
+    locals: ThreadLocal<Local<K, V, S>>,
+}
+
+impl<K, V> SharedMap<K, V, RandomState>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+{
+    /// Creates a new, shared map.
+    ///
+    /// See [`Map::with_capacity`] for the meaning of `nbuckets`.
+    /// To configure the map further, create it with [`Map::builder`], and convert the returned
+    /// handle with `SharedMap::from`.
+    pub fn with_capacity(nbuckets: usize) -> Self {
+        Self::with_capacity_and_hasher(nbuckets, RandomState::new())
+    }
+}
+
+impl<K, V, S> SharedMap<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send + Sync,
+{
+    /// Creates a new, shared map that uses `hash_builder` to hash keys.
+    ///
+    /// See [`Map::with_capacity`] for the meaning of `nbuckets`.
+    pub fn with_capacity_and_hasher(nbuckets: usize, hash_builder: S) -> Self {
+        Map::with_capacity_and_hasher(nbuckets, hash_builder).into()
+    }
+
+    /// Returns a new handle to the map, for threads that want to avoid the cost of looking up
+    /// their handle on every operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::SharedMap;
+    ///
+    /// let map = SharedMap::with_capacity(16);
+    /// map.insert(1, "a");
+    ///
+    /// let mut handle = map.handle();
+    /// assert_eq!(handle.insert(1, "b"), Some("a"));
+    /// assert_eq!(map.get(&1), Some("b"));
+    /// ```
+    pub fn handle(&self) -> MapHandle<K, V, S> {
+        MapHandle::register(Arc::clone(&self.map))
+    }
+
+    /// Runs `f` with the current thread's handle to the map.
+    fn with_handle<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut MapHandle<K, V, S>) -> R,
+    {
+        let local = self.locals.get_or(|| Local {
+            depth: Cell::new(0),
+            idle: RefCell::new(Vec::new()),
+        });
+
+        let depth = local.depth.get();
+        let handle = local.idle.borrow_mut().pop();
+        let handle = handle.unwrap_or_else(|| self.handle());
+        local.depth.set(depth + 1);
+
+        let mut slot = Slot {
+            local,
+            depth,
+            handle: Some(handle),
+        };
+        f(slot.handle.as_mut().unwrap())
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.map.table.len()
+    }
+
+    /// Returns true if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A handle that is in use by the current thread, and is given back once the operation finishes,
+/// even if it panics.
+struct Slot<'a, K: 'a, V: 'a, S: 'a> {
+    local: &'a Local<K, V, S>,
+    depth: usize,
+    handle: Option<MapHandle<K, V, S>>,
+}
+
+impl<'a, K, V, S> Drop for Slot<'a, K, V, S> {
+    fn drop(&mut self) {
+        let mut handle = self.handle.take().unwrap();
+        self.local.depth.set(self.depth);
+        let mut idle = self.local.idle.borrow_mut();
+        if self.depth == 0 {
+            // handles used by nested operations only clean up when they are used again, which may
+            // be never, so take over their garbage
+            for nested in idle.iter_mut() {
+                handle.adopt(nested);
+            }
+        }
+        idle.push(handle);
+    }
+}
+
+impl<K, V, S> SharedMap<K, V, S>
+where
+    K: Hash + Eq + Send + Sync,
+    V: Send + Sync,
+    S: BuildHasher + Send + Sync,
+{
+    /// Inserts a key-value pair into the map, and returns the value it replaced, if any.
+    ///
+    /// See [`MapHandle::insert`].
+    pub fn insert(&self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.with_handle(|h| h.insert(key, value))
+    }
+
+    /// Inserts a key-value pair into the map only if the key is not already present.
+    ///
+    /// See [`MapHandle::try_insert`].
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), V> {
+        self.with_handle(|h| h.try_insert(key, value))
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting `value` if the key
+    /// is not already present.
+    ///
+    /// See [`MapHandle::get_or_insert`].
+    pub fn get_or_insert(&self, key: K, value: V) -> V
+    where
+        V: Clone,
+    {
+        self.with_handle(|h| h.get_or_insert(key, value))
+    }
+
+    /// Returns a clone of the value corresponding to the key, first inserting the value returned
+    /// by `make` if the key is not already present.
+    ///
+    /// See [`MapHandle::get_or_insert_with`].
+    pub fn get_or_insert_with<F>(&self, key: K, make: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        self.with_handle(|h| h.get_or_insert_with(key, make))
+    }
+
+    /// Returns `true` if the map contains a value for the key.
+    ///
+    /// See [`MapHandle::contains_key`].
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.with_handle(|h| h.contains_key(key))
+    }
+
+    /// Returns a copy of the value corresponding to the key.
+    ///
+    /// See [`MapHandle::get`].
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Copy,
+    {
+        self.with_handle(|h| h.get(key))
+    }
+
+    /// Returns a clone of the value corresponding to the key.
+    ///
+    /// See [`MapHandle::get_cloned`].
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.with_handle(|h| h.get_cloned(key))
+    }
+
+    /// Applies a function to the value corresponding to the key, and returns the result.
+    ///
+    /// `then` may itself use the map, although memory that is retired by the operations it
+    /// performs cannot be reclaimed until the outermost operation has finished. See
+    /// [`MapHandle::get_and`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use concache::manual::SharedMap;
+    ///
+    /// let map = SharedMap::with_capacity(16);
+    /// map.insert(1, 2);
+    /// map.get_and(&1, |v| map.insert(*v, 3));
+    /// assert_eq!(map.get(&2), Some(3));
+    /// ```
+    pub fn get_and<Q, F, R>(&self, key: &Q, then: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        self.with_handle(|h| h.get_and(key, then))
+    }
+
+    /// Atomically replaces the value corresponding to the key with the result of applying `f` to
+    /// it, and returns a clone of the new value.
+    ///
+    /// See [`MapHandle::update`].
+    pub fn update<Q, F>(&self, key: &Q, f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> V,
+        V: Clone,
+    {
+        self.with_handle(|h| h.update(key, f))
+    }
+
+    /// Atomically replaces the value corresponding to the key with `new`, but only if the current
+    /// value is equal to `expected`.
+    ///
+    /// See [`MapHandle::compare_exchange`].
+    pub fn compare_exchange<Q>(&self, key: &Q, expected: &V, new: V) -> Result<(), Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        self.with_handle(|h| h.compare_exchange(key, expected, new))
+    }
+
+    /// Atomically sets the value corresponding to the key to the result of applying `f` to the
+    /// current value, and returns a clone of the new value.
+    ///
+    /// See [`MapHandle::compute`].
+    pub fn compute<F>(&self, key: K, f: F) -> Option<V>
+    where
+        F: FnMut(Option<&V>) -> Option<V>,
+        V: Clone,
+    {
+        self.with_handle(|h| h.compute(key, f))
+    }
+
+    /// Removes a key from the map, and returns its value if the key was present.
+    ///
+    /// See [`MapHandle::remove`].
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.with_handle(|h| h.remove(key))
+    }
+
+    /// Removes a key from the map if `pred` returns `true` for its value.
+    ///
+    /// See [`MapHandle::remove_if`].
+    pub fn remove_if<Q, F>(&self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnMut(&V) -> bool,
+        V: Clone,
+    {
+        self.with_handle(|h| h.remove_if(key, pred))
+    }
+
+    /// Removes a key from the map if its value is equal to `expected`.
+    ///
+    /// See [`MapHandle::remove_if_eq`].
+    pub fn remove_if_eq<Q>(&self, key: &Q, expected: &V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone + PartialEq,
+    {
+        self.with_handle(|h| h.remove_if_eq(key, expected))
+    }
+}
+
+impl<K, V, S> From<MapHandle<K, V, S>> for SharedMap<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send + Sync,
+{
+    fn from(handle: MapHandle<K, V, S>) -> Self {
+        SharedMap {
+            map: Arc::clone(&handle.map),
+            locals: ThreadLocal::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::REFRESH_RATE;
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread;
+
+    #[test]
+    fn sharedmap_concurr() {
+        let map = Arc::new(SharedMap::with_capacity(16));
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    for i in 0..2000 {
+                        let key = t * 2000 + i;
+                        map.insert(key, key);
+                        assert_eq!(map.get(&key), Some(key));
+                        if i % 2 == 0 {
+                            assert_eq!(map.remove(&key), Some(key));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(map.len(), 8000);
+        for key in 0..16000 {
+            assert_eq!(map.get(&key), if key % 2 == 0 { None } else { Some(key) });
+        }
+    }
+
+    #[test]
+    fn sharedmap_reentrant() {
+        let map = SharedMap::with_capacity(16);
+        map.insert(0, 0);
+
+        // enough nested removals to trigger cleanups, which must not wait for the outer operation
+        map.get_and(&0, |_| {
+            for i in 1..=2 * REFRESH_RATE {
+                map.insert(i, i);
+                map.remove(&i);
+            }
+        });
+        assert_eq!(map.len(), 1);
+
+        let garbage = |map: &SharedMap<usize, usize>| -> Vec<usize> {
+            let idle = map.locals.get().unwrap().idle.borrow();
+            idle.iter()
+                .map(|h| h.retired.vals.len() + h.limbo.iter().map(|b| b.vals.len()).sum::<usize>())
+                .collect()
+        };
+        // the nested handle has handed its garbage to the outer one
+        let held = garbage(&map);
+        assert_eq!(held.len(), 2);
+        assert_eq!(held.iter().filter(|&&n| n != 0).count(), 1);
+
+        for _ in 0..REFRESH_RATE {
+            map.get(&0);
+        }
+        assert!(garbage(&map).iter().all(|&n| n == 0));
+    }
+
+    #[test]
+    fn sharedmap_panic() {
+        let map = Arc::new(SharedMap::with_capacity(16));
+        map.insert(0, 0);
+
+        let caught = panic::catch_unwind(AssertUnwindSafe(|| {
+            map.get_and(&0, |_| panic!("oops"));
+        }));
+        assert!(caught.is_err());
+
+        // the panicking thread's handle must not hold up reclamation on other threads
+        let other = Arc::clone(&map);
+        thread::spawn(move || {
+            for i in 1..=2 * REFRESH_RATE {
+                other.insert(i, i);
+                other.remove(&i);
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(map.get(&0), Some(0));
+    }
+}
@@ -0,0 +1,111 @@
+//! The interface between the lock-free [`Table`](::table::Table) that all of the maps are built
+//! on, and the memory reclamation schemes that decide when memory unlinked from it is freed.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use table::{Buckets, Node};
+
+/// The number of pointers a table operation may need to have protected at the same time.
+pub(crate) const SLOTS: usize = 6;
+
+/// The slot protecting the node before the one a search is looking at.
+pub(crate) const PREV: usize = 0;
+/// The slot protecting the node a search is looking at.
+pub(crate) const CUR: usize = 1;
+/// The slot protecting the value of the node in [`CUR`].
+pub(crate) const VAL: usize = 2;
+/// The slot protecting the bucket directory.
+pub(crate) const DIR: usize = 3;
+/// The slot protecting the node an iterator is at, across searches.
+pub(crate) const ANCHOR: usize = 4;
+/// The slot protecting a value that an operation installs in place of the one in [`VAL`], which
+/// has to stay protected until the value is installed.
+pub(crate) const NEW: usize = 5;
+
+/// A memory reclamation scheme, as used by one thread to operate on a [`Table`](::table::Table).
+///
+/// The table unlinks nodes, values and bucket directories while other threads may still be
+/// reading them, and hands them to the `Reclaimer` to free once that is no longer the case. Every
+/// table operation happens between a call to [`Reclaimer::enter`] and one to
+/// [`Reclaimer::exit`], and pointers the operation returns stay valid until then.
+///
+/// Within an operation, the table publishes every pointer it is about to dereference with
+/// [`Reclaimer::protect`], and then checks that the pointer is still reachable from where it was
+/// loaded. Schemes that keep everything alive for the whole critical section can ignore this,
+/// while hazard pointers need nothing else.
+pub(crate) trait Reclaimer<K, V> {
+    /// Enters a critical section, before the start of a table operation.
+    fn enter(&mut self);
+
+    /// Leaves the critical section. Nothing the operation loaded may be used after this.
+    fn exit(&mut self);
+
+    /// Publishes `ptr` in `slot`, one of [`PREV`], [`CUR`], [`VAL`], [`DIR`], [`ANCHOR`] or
+    /// [`NEW`], and drops the protection of the pointer that was in that slot before.
+    ///
+    /// `ptr` may only be dereferenced after it has been read again from where it was loaded, to
+    /// check that it was not unlinked before it was published.
+    fn protect<T>(&mut self, slot: usize, ptr: *mut T);
+
+    /// Loads a pointer from `src` and protects it in `slot`, retrying until it is known to have
+    /// still been in `src` after it was published.
+    fn load<T>(&mut self, slot: usize, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::SeqCst);
+        loop {
+            self.protect(slot, ptr);
+            let again = src.load(Ordering::SeqCst);
+            if again == ptr {
+                return ptr;
+            }
+            ptr = again;
+        }
+    }
+
+    /// Takes ownership of a node that has been unlinked from the list, and frees it once no
+    /// thread can be reading it anymore. The node's value has already been taken out.
+    fn retire_node(&mut self, node: *mut Node<K, V>);
+
+    /// Takes ownership of a value that has been replaced or removed, and frees it once no thread
+    /// can be reading it anymore.
+    fn retire_value(&mut self, value: *mut V);
+
+    /// Takes ownership of a bucket directory that has been replaced by a larger one, and frees it
+    /// once no thread can be reading it anymore.
+    fn retire_buckets(&mut self, buckets: *mut Buckets<K, V>);
+}
+
+/// Leaves the critical section of the reclaimer it holds when it is dropped.
+struct Exit<'r, K, V, R>
+where
+    R: Reclaimer<K, V> + 'r,
+{
+    r: &'r mut R,
+    _entries: PhantomData<fn(K, V)>,
+}
+
+impl<'r, K, V, R> Drop for Exit<'r, K, V, R>
+where
+    R: Reclaimer<K, V> + 'r,
+{
+    fn drop(&mut self) {
+        self.r.exit();
+    }
+}
+
+/// Runs `f` in a critical section of `r`.
+///
+/// The critical section is left even if `f` panics, since nothing `f` loaded outlives the
+/// unwinding. Otherwise a thread that catches the panic would stay in it for good, and keep other
+/// threads from reclaiming anything.
+pub(crate) fn critical<K, V, R, F, T>(r: &mut R, f: F) -> T
+where
+    R: Reclaimer<K, V>,
+    F: FnOnce(&mut R) -> T,
+{
+    r.enter();
+    let exit = Exit {
+        r,
+        _entries: PhantomData,
+    };
+    f(exit.r)
+}
@@ -0,0 +1,837 @@
+use reclaim::{Reclaimer, CUR, NEW, PREV, VAL};
+use std::borrow::Borrow;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+const OSC: Ordering = Ordering::SeqCst;
+
+/// A node in the split-ordered list.
+///
+/// `hash` is the node's split-order key: the bit-reversed hash of the key for regular nodes, and
+/// the bit-reversed bucket index for the dummy nodes that mark the start of each bucket. Dummy
+/// nodes have no key and no value, and are never removed from the list.
+///
+/// A node is logically deleted once its value is swapped out for null. Its `next` pointer is then
+/// marked, after which the node can be unlinked.
+///
+/// `referenced` is the reference bit of the CLOCK eviction in [`LinkedList::evict`]. It is set
+/// whenever a search finds the node's key, and cleared when the hand passes the node.
+pub(crate) struct Node<K, V> {
+    pub(super) hash: usize,
+    pub(super) key: Option<K>,
+    val: AtomicPtr<V>,
+    next: AtomicPtr<Node<K, V>>,
+    referenced: AtomicBool,
+}
+
+impl<K, V> Node<K, V> {
+    fn dummy(hash: usize) -> Self {
+        Node {
+            hash,
+            key: None,
+            val: AtomicPtr::new(ptr::null_mut()),
+            next: AtomicPtr::new(ptr::null_mut()),
+            referenced: AtomicBool::new(false),
+        }
+    }
+
+    fn new(hash: usize, key: K, val: V) -> Self {
+        Node {
+            hash,
+            key: Some(key),
+            val: AtomicPtr::new(Box::into_raw(Box::new(val))),
+            next: AtomicPtr::new(ptr::null_mut()),
+            referenced: AtomicBool::new(true),
+        }
+    }
+
+    fn vacant(hash: usize, key: K) -> Self {
+        Node {
+            hash,
+            key: Some(key),
+            val: AtomicPtr::new(ptr::null_mut()),
+            next: AtomicPtr::new(ptr::null_mut()),
+            referenced: AtomicBool::new(true),
+        }
+    }
+
+    /// Sets the reference bit of this node.
+    ///
+    /// The bit is only written if it is clear. New nodes start out with the bit set, so lookups in
+    /// a table that never evicts do not write to the nodes they find.
+    fn touch(&self) {
+        if !self.referenced.load(OSC) {
+            self.referenced.store(true, OSC);
+        }
+    }
+
+    /// Whether this node holds exactly `(hash, key)`.
+    fn matches<Q>(&self, hash: usize, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.hash == hash && self.key.as_ref().map(Borrow::borrow) == Some(key)
+    }
+
+    /// Whether this node is where a search for `(hash, key)` should stop.
+    ///
+    /// Keys are only ordered by their hash, so a search walks over any other keys with the same
+    /// hash, and new keys are inserted at the end of the run of keys with equal hashes.
+    fn at_or_after<Q>(&self, hash: usize, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.hash > hash || self.matches(hash, key)
+    }
+}
+
+/// A single lock-free linked list holding every node of the table, sorted by split-order key.
+///
+/// Nodes are deleted as described by
+/// [Harris](https://www.microsoft.com/en-us/research/wp-content/uploads/2001/10/2001-disc.pdf),
+/// by first marking their `next` pointer and then unlinking them, but searches follow [Michael's
+/// variant](https://dl.acm.org/citation.cfm?id=564870) of the algorithm: a search unlinks every
+/// marked node it comes across before moving on, so that it only ever steps from a node that is
+/// still in the list to its successor. That is what makes it possible to protect each node with a
+/// hazard pointer before dereferencing it.
+///
+/// Every operation takes the [`Reclaimer`] of the calling thread, which it publishes the pointers
+/// it dereferences with, and which it retires the nodes and values it unlinks into.
+pub(super) struct LinkedList<K, V> {
+    head: AtomicPtr<Node<K, V>>,
+}
+
+impl<K, V> Default for LinkedList<K, V> {
+    fn default() -> Self {
+        LinkedList {
+            head: AtomicPtr::new(Box::into_raw(Box::new(Node::dummy(0)))),
+        }
+    }
+}
+
+impl<K, V> Drop for LinkedList<K, V> {
+    fn drop(&mut self) {
+        // no thread can be accessing the list anymore, so free every node that is still linked
+        // into it. nodes that have been unlinked were retired by whoever unlinked them.
+        let mut node = self.head.load(OSC);
+        while !node.is_null() {
+            let n = unsafe { Box::from_raw(node) };
+            let val = n.val.load(OSC);
+            if !val.is_null() {
+                drop(unsafe { Box::from_raw(val) });
+            }
+            node = Self::get_unmarked_reference(n.next.load(OSC));
+        }
+    }
+}
+
+impl<K, V> LinkedList<K, V> {
+    /// The dummy node of bucket 0, which is also the head of the list.
+    pub(super) fn head(&self) -> *mut Node<K, V> {
+        self.head.load(OSC)
+    }
+
+    /// Returns the node after `node`, protected in [`CUR`], or null at the end of the list.
+    ///
+    /// `node` must be protected. Returns `None` if `node` has been deleted, since its successor may
+    /// then be unlinked and freed at any time.
+    pub(super) fn successor<R>(&self, r: &mut R, node: *mut Node<K, V>) -> Option<*mut Node<K, V>>
+    where
+        R: Reclaimer<K, V>,
+    {
+        let n = unsafe { &*node };
+        loop {
+            let next = n.next.load(OSC);
+            if Self::is_marked_reference(next) {
+                return None;
+            }
+            r.protect(CUR, next);
+            if n.next.load(OSC) == next {
+                return Some(next);
+            }
+        }
+    }
+
+    /// Returns the value of `node`, protected in [`VAL`], or null if the node has been deleted.
+    ///
+    /// `node` must be protected.
+    pub(super) fn value<R>(r: &mut R, node: *mut Node<K, V>) -> *mut V
+    where
+        R: Reclaimer<K, V>,
+    {
+        r.load(VAL, &unsafe { &*node }.val)
+    }
+
+    fn is_marked_reference(ptr: *mut Node<K, V>) -> bool {
+        (ptr as usize & 0x1) == 1
+    }
+    fn get_marked_reference(ptr: *mut Node<K, V>) -> *mut Node<K, V> {
+        (ptr as usize | 0x1) as *mut _
+    }
+    fn get_unmarked_reference(ptr: *mut Node<K, V>) -> *mut Node<K, V> {
+        (ptr as usize & !0x1) as *mut _
+    }
+
+    /// Marks the next pointer of `node`, unless it is already marked, so that no node can be
+    /// inserted after it and searches will unlink it.
+    fn mark(node: *mut Node<K, V>) {
+        let n = unsafe { &*node };
+        loop {
+            let next = n.next.load(OSC);
+            if Self::is_marked_reference(next)
+                || n.next
+                    .compare_exchange(next, Self::get_marked_reference(next), OSC, OSC)
+                    .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Inserts the dummy node with split-order key `hash`, searching from the dummy node of its
+    /// parent bucket, and returns it. If another thread has already inserted that dummy node, the
+    /// existing one is returned instead.
+    pub(super) fn insert_dummy<R>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+    ) -> *mut Node<K, V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        let new_node = Box::into_raw(Box::new(Node::dummy(hash)));
+
+        loop {
+            // regular nodes all have odd split-order keys, so the only node with this key is the
+            // dummy node itself
+            let (left, right) = self.search(r, start, |n| n.hash >= hash);
+            if !right.is_null() && unsafe { &*right }.hash == hash {
+                drop(unsafe { Box::from_raw(new_node) });
+                return right;
+            }
+
+            unsafe { &*new_node }.next.store(right, OSC);
+            if unsafe { &*left }
+                .next
+                .compare_exchange(right, new_node, OSC, OSC)
+                .is_ok()
+            {
+                return new_node;
+            }
+        }
+    }
+
+    /// Returns the first node that has not been marked and for which `stop` holds, protected in
+    /// [`CUR`], or null if there is no such node, along with the node before it.
+    ///
+    /// `stop` must hold for every node after the first one it holds for. The node before the
+    /// returned one is either `start` or protected in [`PREV`], and was not marked when the search
+    /// passed it. Marked nodes the search comes across are unlinked and retired.
+    pub(super) fn search<R, F>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        stop: F,
+    ) -> (*mut Node<K, V>, *mut Node<K, V>)
+    where
+        R: Reclaimer<K, V>,
+        F: Fn(&Node<K, V>) -> bool,
+    {
+        'search_again: loop {
+            // dummy nodes are never deleted, so it is always safe to restart from `start`
+            let mut left = start;
+            let mut right = Self::get_unmarked_reference(unsafe { &*left }.next.load(OSC));
+
+            loop {
+                if right.is_null() {
+                    return (left, right);
+                }
+
+                // `left` is protected, so if it still points to `right` once `right` is protected,
+                // neither can have been unlinked yet
+                r.protect(CUR, right);
+                if unsafe { &*left }.next.load(OSC) != right {
+                    continue 'search_again;
+                }
+
+                let next = unsafe { &*right }.next.load(OSC);
+                if Self::is_marked_reference(next) {
+                    if unsafe { &*left }
+                        .next
+                        .compare_exchange(right, Self::get_unmarked_reference(next), OSC, OSC)
+                        .is_err()
+                    {
+                        continue 'search_again;
+                    }
+                    r.retire_node(right);
+                    right = Self::get_unmarked_reference(next);
+                    continue;
+                }
+
+                if stop(unsafe { &*right }) {
+                    return (left, right);
+                }
+
+                // `right` is already protected, so it stays protected while it moves over
+                r.protect(PREV, right);
+                left = right;
+                right = next;
+            }
+        }
+    }
+
+    /// Moves the hand of a CLOCK eviction over the bucket that starts at the dummy node `start`,
+    /// and removes the first key that has not been found since the hand last passed it. Returns
+    /// the removed value, which has been retired, or `None` if every key in the bucket has been
+    /// found since.
+    ///
+    /// Every key the hand passes gets a second chance: its reference bit is cleared, so that it is
+    /// removed the next time around unless it is found again before then. A search that has to
+    /// start over sees the bits it already cleared, so it may stop at a key it passed before.
+    pub(super) fn evict<R>(&self, r: &mut R, start: *mut Node<K, V>) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        loop {
+            // the window is never inserted into, so it does not matter that `stop` may not hold
+            // for the keys after the one it first holds for
+            let (left, right) = self.search(r, start, |n| {
+                n.key.is_none() || !n.referenced.swap(false, OSC)
+            });
+            if right.is_null() || unsafe { &*right }.key.is_none() {
+                // the end of the bucket
+                return None;
+            }
+
+            let val = Self::value(r, right);
+            if val.is_null() {
+                // removed by another thread, but not yet marked. the next search unlinks it.
+                Self::mark(right);
+                continue;
+            }
+
+            // if the value is replaced first, the key has been found again and gets another chance
+            if unsafe { &*right }
+                .val
+                .compare_exchange(val, ptr::null_mut(), OSC, OSC)
+                .is_ok()
+            {
+                self.unlink(r, start, left, right);
+                r.retire_value(val);
+                return Some(val);
+            }
+        }
+    }
+
+    /// Marks and unlinks `node`, whose value the caller has just swapped out for null.
+    fn unlink<R>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        left: *mut Node<K, V>,
+        node: *mut Node<K, V>,
+    ) where
+        R: Reclaimer<K, V>,
+    {
+        Self::mark(node);
+
+        let next = Self::get_unmarked_reference(unsafe { &*node }.next.load(OSC));
+        if unsafe { &*left }
+            .next
+            .compare_exchange(node, next, OSC, OSC)
+            .is_ok()
+        {
+            r.retire_node(node);
+        } else {
+            // a search unlinks every marked node it passes, and the node is somewhere in the run
+            // of nodes with its hash
+            let hash = unsafe { &*node }.hash;
+            let _ = self.search(r, start, |n| n.hash > hash);
+        }
+    }
+}
+
+impl<K, V> LinkedList<K, V>
+where
+    K: Eq,
+{
+    /// Inserts a key-value pair, or replaces the value if the key is already present.
+    ///
+    /// The replaced value is returned, and has been retired.
+    pub(super) fn insert<R>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: K,
+        val: V,
+    ) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        let new_node = Box::into_raw(Box::new(Node::new(hash, key, val)));
+        let key = unsafe { &*new_node }.key.as_ref().unwrap();
+
+        loop {
+            match self.find(r, start, hash, key) {
+                Ok((_, right, old)) => {
+                    // the key is already present, so move our value into the existing node. the
+                    // new node was never shared, so it can be freed right away.
+                    let new = unsafe { &*new_node }.val.load(OSC);
+                    if unsafe { &*right }
+                        .val
+                        .compare_exchange(old, new, OSC, OSC)
+                        .is_ok()
+                    {
+                        drop(unsafe { Box::from_raw(new_node) });
+                        r.retire_value(old);
+                        return Some(old);
+                    }
+                }
+                Err((left, right)) => {
+                    unsafe { &*new_node }.next.store(right, OSC);
+                    if unsafe { &*left }
+                        .next
+                        .compare_exchange(right, new_node, OSC, OSC)
+                        .is_ok()
+                    {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Inserts a key with the value returned by `make`, unless the key is already present.
+    ///
+    /// Returns the inserted value if the key was inserted. Otherwise, returns the value already in
+    /// the list, along with the value `make` produced if it was called before another thread
+    /// inserted the key. `make` is called at most once. The returned value is protected.
+    pub(super) fn insert_new<R, F>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: K,
+        make: F,
+    ) -> Result<*mut V, (*mut V, Option<V>)>
+    where
+        R: Reclaimer<K, V>,
+        F: FnOnce() -> V,
+    {
+        let new_node = Box::into_raw(Box::new(Node::vacant(hash, key)));
+        let key = unsafe { &*new_node }.key.as_ref().unwrap();
+        let mut make = Some(make);
+
+        loop {
+            let (left, right) = match self.find(r, start, hash, key) {
+                Ok((_, _, val)) => {
+                    // someone else got there first; our node was never shared
+                    let n: Box<Node<K, V>> = unsafe { Box::from_raw(new_node) };
+                    let v = n.val.load(OSC);
+                    let unused = if v.is_null() {
+                        None
+                    } else {
+                        Some(*unsafe { Box::from_raw(v) })
+                    };
+                    return Err((val, unused));
+                }
+                Err(window) => window,
+            };
+
+            let n = unsafe { &*new_node };
+            if let Some(make) = make.take() {
+                n.val.store(Box::into_raw(Box::new(make())), OSC);
+            }
+            // the value can be replaced and retired as soon as the node is linked in, so it has
+            // to be protected before then
+            let val = n.val.load(OSC);
+            r.protect(VAL, val);
+            n.next.store(right, OSC);
+            if unsafe { &*left }
+                .next
+                .compare_exchange(right, new_node, OSC, OSC)
+                .is_ok()
+            {
+                return Ok(val);
+            }
+        }
+    }
+
+    /// Looks up a key, and returns its value, protected in [`VAL`], if it is present.
+    pub(super) fn get<R, Q>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: &Q,
+    ) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.find(r, start, hash, key).ok().map(|(_, _, val)| val)
+    }
+
+    /// Replaces the value of a key with the value `f` returns for its current value, retrying until
+    /// no other thread has changed the value between reading it and installing the result. If `f`
+    /// returns `None`, the value is left as it is.
+    ///
+    /// Returns the new value, which is protected in [`NEW`]; the replaced value has been retired.
+    /// If nothing was replaced, returns the current value, if any, protected in [`VAL`] instead.
+    pub(super) fn update<R, Q, F>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: &Q,
+        mut f: F,
+    ) -> Result<*mut V, Option<*mut V>>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        F: FnMut(&V) -> Option<V>,
+    {
+        loop {
+            let (_, right, old) = self.find(r, start, hash, key).map_err(|_| None)?;
+
+            let new = match f(unsafe { &*old }) {
+                Some(v) => Box::into_raw(Box::new(v)),
+                None => return Err(Some(old)),
+            };
+            // the new value can be retired as soon as it is installed, and the caller still
+            // needs it. the old value has to stay protected until the exchange: if it were freed,
+            // its memory could be reused for a value another thread installs in the same node, and
+            // the exchange would succeed against a value `f` never saw.
+            r.protect(NEW, new);
+            if unsafe { &*right }
+                .val
+                .compare_exchange(old, new, OSC, OSC)
+                .is_ok()
+            {
+                r.retire_value(old);
+                return Ok(new);
+            }
+            drop(unsafe { Box::from_raw(new) });
+        }
+    }
+
+    /// Sets the value of a key to `f` applied to its current value, or to `None` if the key is not
+    /// present. The key is inserted or removed if `f` turns a `None` into a `Some` or vice versa.
+    /// Like [`LinkedList::update`], this retries until the result is installed against the value
+    /// `f` was given.
+    ///
+    /// Returns the previous value, which has been retired, and the new value, which is protected in
+    /// [`NEW`].
+    pub(super) fn compute<R, F>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: K,
+        mut f: F,
+    ) -> (Option<*mut V>, Option<*mut V>)
+    where
+        R: Reclaimer<K, V>,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        let new_node = Box::into_raw(Box::new(Node::vacant(hash, key)));
+        let key = unsafe { &*new_node }.key.as_ref().unwrap();
+
+        loop {
+            match self.find(r, start, hash, key) {
+                Ok((left, right, old)) => {
+                    let new = f(Some(unsafe { &*old }))
+                        .map(|v| Box::into_raw(Box::new(v)))
+                        .unwrap_or_else(ptr::null_mut);
+                    // as in `update`, the old value stays protected until the exchange
+                    r.protect(NEW, new);
+                    if unsafe { &*right }
+                        .val
+                        .compare_exchange(old, new, OSC, OSC)
+                        .is_ok()
+                    {
+                        if new.is_null() {
+                            self.unlink(r, start, left, right);
+                        }
+                        drop(unsafe { Box::from_raw(new_node) });
+                        r.retire_value(old);
+                        return (Some(old), Some(new).filter(|v| !v.is_null()));
+                    }
+                    if !new.is_null() {
+                        drop(unsafe { Box::from_raw(new) });
+                    }
+                }
+                Err((left, right)) => {
+                    let new = match f(None) {
+                        Some(v) => Box::into_raw(Box::new(v)),
+                        None => {
+                            drop(unsafe { Box::from_raw(new_node) });
+                            return (None, None);
+                        }
+                    };
+                    r.protect(NEW, new);
+                    let n = unsafe { &*new_node };
+                    n.val.store(new, OSC);
+                    n.next.store(right, OSC);
+                    if unsafe { &*left }
+                        .next
+                        .compare_exchange(right, new_node, OSC, OSC)
+                        .is_ok()
+                    {
+                        return (None, Some(new));
+                    }
+                    drop(unsafe { Box::from_raw(n.val.swap(ptr::null_mut(), OSC)) });
+                }
+            }
+        }
+    }
+
+    /// Removes a key if `pred` holds for its current value, returning the value if the key was
+    /// removed. The value has been retired.
+    ///
+    /// A node is logically deleted as soon as its value is swapped out for null; this is the point
+    /// at which the removal takes effect. The node is then marked and unlinked from the list.
+    pub(super) fn delete<R, Q, F>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: &Q,
+        mut pred: F,
+    ) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        F: FnMut(&V) -> bool,
+    {
+        loop {
+            let (left, right, old) = self.find(r, start, hash, key).ok()?;
+
+            if !pred(unsafe { &*old }) {
+                return None;
+            }
+
+            if unsafe { &*right }
+                .val
+                .compare_exchange(old, ptr::null_mut(), OSC, OSC)
+                .is_ok()
+            {
+                self.unlink(r, start, left, right);
+                r.retire_value(old);
+                return Some(old);
+            }
+        }
+    }
+
+    /// Searches for a key that has not been deleted, and returns its node, protected in [`CUR`],
+    /// and its current value, protected in [`VAL`], along with the node before it.
+    ///
+    /// If the key is not present, the window the key would have to be inserted into is returned
+    /// instead. See [`LinkedList::search`].
+    #[allow(clippy::type_complexity)]
+    fn find<R, Q>(
+        &self,
+        r: &mut R,
+        start: *mut Node<K, V>,
+        hash: usize,
+        key: &Q,
+    ) -> Result<(*mut Node<K, V>, *mut Node<K, V>, *mut V), (*mut Node<K, V>, *mut Node<K, V>)>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        loop {
+            let (left, right) = self.search(r, start, |n| n.at_or_after(hash, key));
+            if right.is_null() || !unsafe { &*right }.matches(hash, key) {
+                return Err((left, right));
+            }
+
+            let val = Self::value(r, right);
+            if !val.is_null() {
+                unsafe { &*right }.touch();
+                return Ok((left, right, val));
+            }
+
+            // the node has been logically deleted, but not yet marked. help out, so that the
+            // next search unlinks it.
+            Self::mark(right);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use reclaim::SLOTS;
+    use table::Buckets;
+
+    /// A reclaimer for a single thread, which records what is protected, and frees what is
+    /// retired once it is dropped.
+    #[derive(Default)]
+    pub(in table) struct Recorder<K, V> {
+        pub(in table) slots: [usize; SLOTS],
+        pub(in table) nodes: Vec<*mut Node<K, V>>,
+        pub(in table) vals: Vec<*mut V>,
+    }
+
+    impl<K, V> Reclaimer<K, V> for Recorder<K, V> {
+        fn enter(&mut self) {}
+
+        fn exit(&mut self) {
+            self.slots = [0; SLOTS];
+        }
+
+        fn protect<T>(&mut self, slot: usize, ptr: *mut T) {
+            self.slots[slot] = ptr as usize;
+        }
+
+        fn retire_node(&mut self, node: *mut Node<K, V>) {
+            self.nodes.push(node);
+        }
+
+        fn retire_value(&mut self, value: *mut V) {
+            self.vals.push(value);
+        }
+
+        fn retire_buckets(&mut self, buckets: *mut Buckets<K, V>) {
+            drop(unsafe { Box::from_raw(buckets) });
+        }
+    }
+
+    impl<K, V> Drop for Recorder<K, V> {
+        fn drop(&mut self) {
+            for &node in &self.nodes {
+                drop(unsafe { Box::from_raw(node) });
+            }
+            for &val in &self.vals {
+                drop(unsafe { Box::from_raw(val) });
+            }
+        }
+    }
+
+    // regular split-order keys are odd, so use the key itself as its own hash
+    fn so(key: usize) -> usize {
+        key << 1 | 1
+    }
+
+    #[test]
+    fn linkedlist_basics() {
+        let mut r = Recorder::default();
+
+        let list = LinkedList::default();
+        let h = list.head();
+        let get = |key: usize, r: &mut Recorder<_, _>| {
+            list.get(r, h, so(key), &key).map(|v| unsafe { *v })
+        };
+
+        assert_eq!(list.insert(&mut r, h, so(3), 3, 2), None);
+        let old = list.insert(&mut r, h, so(3), 3, 4).unwrap();
+        assert_eq!(r.vals, vec![old]);
+        assert_eq!(unsafe { *old }, 2);
+        list.insert(&mut r, h, so(5), 5, 8);
+        list.insert(&mut r, h, so(1), 1, 8);
+
+        assert_eq!(get(3, &mut r), Some(4));
+        assert_eq!(get(5, &mut r), Some(8));
+        assert_eq!(get(2, &mut r), None);
+
+        let old = list.delete(&mut r, h, so(5), &5, |_| true).unwrap();
+        assert_eq!(unsafe { *old }, 8);
+        assert_eq!(get(5, &mut r), None);
+        assert_eq!(r.nodes.len(), 1);
+        assert_eq!(r.vals.len(), 2);
+    }
+
+    #[test]
+    fn linkedlist_equal_hashes() {
+        let mut r = Recorder::default();
+
+        // keys are only compared for equality, so keys with the same hash share one run
+        let list = LinkedList::default();
+        let h = list.head();
+        for key in 0..10 {
+            list.insert(&mut r, h, so(1), key, key);
+        }
+        for key in 0..10 {
+            assert_eq!(
+                list.get(&mut r, h, so(1), &key).map(|v| unsafe { *v }),
+                Some(key)
+            );
+        }
+        assert!(list.delete(&mut r, h, so(1), &4, |_| true).is_some());
+        assert_eq!(list.get(&mut r, h, so(1), &4), None);
+        assert!(list.get(&mut r, h, so(1), &5).is_some());
+    }
+
+    #[test]
+    fn linkedlist_evict() {
+        let mut r = Recorder::default();
+
+        let list = LinkedList::default();
+        let h = list.head();
+        for key in 1..5 {
+            list.insert(&mut r, h, so(key), key, key);
+        }
+        let evict = |r: &mut Recorder<_, _>| list.evict(r, h).map(|v| unsafe { *v });
+
+        // new keys count as used, so the first sweep only clears their bits
+        assert_eq!(evict(&mut r), None);
+        assert_eq!(evict(&mut r), Some(1));
+
+        // a key that is found gets another chance. every sweep starts at the start of the
+        // bucket, so it has used that chance up by the next sweep.
+        assert!(list.get(&mut r, h, so(2), &2).is_some());
+        assert_eq!(evict(&mut r), Some(3));
+        assert_eq!(evict(&mut r), Some(2));
+        assert_eq!(evict(&mut r), Some(4));
+        assert_eq!(evict(&mut r), None);
+        assert_eq!(r.nodes.len(), 4);
+    }
+
+    #[test]
+    fn linkedlist_update_protects() {
+        let mut r = Recorder::default();
+
+        let list = LinkedList::default();
+        let h = list.head();
+        list.insert(&mut r, h, so(1), 1, 1);
+
+        // the replaced value stays protected in its slot up to the exchange, and the new one is
+        // protected in a slot of its own
+        let old = list.get(&mut r, h, so(1), &1).unwrap();
+        let new = list.update(&mut r, h, so(1), &1, |v| Some(v + 1)).unwrap();
+        assert_eq!(r.slots[VAL], old as usize);
+        assert_eq!(r.slots[NEW], new as usize);
+
+        let (old, new) = list.compute(&mut r, h, so(1), 1, |v| v.map(|v| v + 1));
+        assert_eq!(r.slots[VAL], old.unwrap() as usize);
+        assert_eq!(r.slots[NEW], new.unwrap() as usize);
+        assert_eq!(unsafe { *new.unwrap() }, 3);
+    }
+
+    #[test]
+    fn linkedlist_search_protects() {
+        let mut r = Recorder::default();
+
+        let list = LinkedList::default();
+        let h = list.head();
+        for key in 0..10 {
+            list.insert(&mut r, h, so(key), key, key);
+        }
+
+        // the window around a key is always protected, apart from the start of the search
+        let (left, right) = list.search(&mut r, h, |n| n.at_or_after(so(5), &5));
+        assert_eq!(unsafe { &*left }.key, Some(4));
+        assert_eq!(unsafe { &*right }.key, Some(5));
+        assert_eq!(r.slots[PREV], left as usize);
+        assert_eq!(r.slots[CUR], right as usize);
+    }
+}
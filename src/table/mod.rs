@@ -0,0 +1,511 @@
+//! The lock-free hash table that all of the maps are built on.
+//!
+//! All keys are kept in a single list ordered by their bit-reversed hashes, and buckets are
+//! shortcuts into that list ([split-ordered lists](https://dl.acm.org/citation.cfm?id=1147958)).
+//! When the average number of keys per bucket grows too large, the number of buckets doubles, and
+//! each new bucket is split off its parent the first time an operation touches it. Nodes never
+//! move during this process, so all operations remain lock-free while the table grows.
+//!
+//! The table does not know how memory is reclaimed. Every operation takes the [`Reclaimer`] of
+//! the calling thread, and the maps differ only in which one they pass in.
+
+use reclaim::{Reclaimer, ANCHOR, DIR};
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use LoadReport;
+
+mod linked_list;
+use self::linked_list::LinkedList;
+pub(crate) use self::linked_list::Node;
+
+const OSC: Ordering = Ordering::SeqCst;
+pub(crate) const MAX_LOAD_FACTOR: usize = 2;
+
+/// Hashes `key` with `hash_builder`, for use with a [`Table`].
+pub(crate) fn hash<S, Q>(hash_builder: &S, key: &Q) -> usize
+where
+    S: BuildHasher,
+    Q: ?Sized + Hash,
+{
+    hash_builder.hash_one(key) as usize
+}
+
+/// The bucket directory of a [`Table`].
+///
+/// Each slot points to the dummy node that starts its bucket in the table's list, or is null if
+/// the bucket has not been initialized yet. When the table outgrows its directory, the directory
+/// is copied into a larger one and the old one is retired like any other garbage.
+pub(crate) struct Buckets<K, V> {
+    slots: Vec<AtomicPtr<Node<K, V>>>,
+}
+
+impl<K, V> Buckets<K, V> {
+    fn new(nslots: usize) -> Self {
+        let mut b = Buckets {
+            slots: Vec::with_capacity(nslots),
+        };
+
+        for _ in 0..nslots {
+            b.slots.push(AtomicPtr::new(ptr::null_mut()));
+        }
+
+        b
+    }
+
+    /// The number of bytes this directory takes up.
+    pub(crate) fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.slots.capacity() * mem::size_of::<AtomicPtr<Node<K, V>>>()
+    }
+}
+
+/// A split-ordered hash table.
+///
+/// Keys are looked up by a hash that the caller computes, with [`hash`] or otherwise, so the
+/// table itself does not depend on the hasher. Values that are returned as raw pointers stay
+/// valid until the reclaimer that was passed in leaves its critical section.
+pub(crate) struct Table<K, V> {
+    list: LinkedList<K, V>,
+    buckets: AtomicPtr<Buckets<K, V>>,
+    nbuckets: AtomicUsize,
+    nitems: AtomicUsize,
+    /// The bucket the hand of [`Table::evict`] moves over next.
+    hand: AtomicUsize,
+}
+
+// the table owns its keys and values, which any thread that shares it can move out, drop, or
+// read through shared references. raw pointers would make it `Send` and `Sync` regardless.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Table<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Table<K, V> {}
+
+impl<K, V> Table<K, V> {
+    pub(crate) fn new(num_of_buckets: usize) -> Self {
+        let nbuckets = num_of_buckets.max(1).next_power_of_two();
+        let list = LinkedList::default();
+
+        let buckets = Buckets::new(nbuckets);
+        buckets.slots[0].store(list.head(), OSC);
+
+        Table {
+            list,
+            buckets: AtomicPtr::new(Box::into_raw(Box::new(buckets))),
+            nbuckets: AtomicUsize::new(nbuckets),
+            nitems: AtomicUsize::new(0),
+            hand: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of keys in the table.
+    pub(crate) fn len(&self) -> usize {
+        self.nitems.load(OSC)
+    }
+
+    /// Returns the number of buckets the table currently has.
+    pub(crate) fn nbuckets(&self) -> usize {
+        self.nbuckets.load(OSC)
+    }
+
+    /// Returns approximately how many bytes of heap the entries in the table take up: a node and
+    /// a boxed value per key, but not any memory owned by the keys and values themselves.
+    pub(crate) fn live_bytes(&self) -> usize {
+        self.len() * (mem::size_of::<Node<K, V>>() + mem::size_of::<V>())
+    }
+
+    /// Returns the bucket index and split-order key of `hash` in a table of `nbuckets` buckets.
+    fn locate(hash: usize, nbuckets: usize) -> (usize, usize) {
+        // regular keys always have their lowest split-order bit set, so they sort strictly after
+        // the dummy node of the bucket they belong to
+        (hash & (nbuckets - 1), hash.reverse_bits() | 1)
+    }
+
+    /// Doubles the number of buckets if the table has grown past its maximum load factor.
+    ///
+    /// Only the bucket directory is touched here; the new buckets are split off their parents
+    /// lazily by the first operation that hashes into them. If the directory has to be replaced,
+    /// the old one is retired.
+    fn maybe_grow<R>(&self, r: &mut R)
+    where
+        R: Reclaimer<K, V>,
+    {
+        let nbuckets = self.nbuckets.load(OSC);
+        if self.nitems.load(OSC) <= nbuckets * MAX_LOAD_FACTOR {
+            return;
+        }
+
+        loop {
+            let buckets_ptr = r.load(DIR, &self.buckets);
+            let buckets = unsafe { &*buckets_ptr };
+            if buckets.slots.len() >= nbuckets * 2 {
+                break;
+            }
+
+            let new_buckets = Buckets::new(nbuckets * 2);
+            for (old, new) in buckets.slots.iter().zip(new_buckets.slots.iter()) {
+                new.store(old.load(OSC), OSC);
+            }
+
+            let new_buckets = Box::into_raw(Box::new(new_buckets));
+            if self
+                .buckets
+                .compare_exchange(buckets_ptr, new_buckets, OSC, OSC)
+                .is_ok()
+            {
+                r.retire_buckets(buckets_ptr);
+                break;
+            }
+            // someone else replaced the directory first
+            drop(unsafe { Box::from_raw(new_buckets) });
+        }
+
+        // if this fails, someone else already grew the table
+        let _ = self
+            .nbuckets
+            .compare_exchange(nbuckets, nbuckets * 2, OSC, OSC);
+    }
+
+    /// Returns the dummy node of `bucket`, initializing the bucket (and, recursively, its parent
+    /// buckets) if no operation has touched it yet.
+    ///
+    /// This is where the table's contents are "migrated" when the table grows: a new bucket is
+    /// split off its parent bucket by inserting a dummy node at the right place in the list.
+    /// Dummy nodes are never removed, so the returned node does not need to be protected.
+    fn bucket<R>(&self, r: &mut R, bucket: usize) -> *mut Node<K, V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        // loading the directory only after `nbuckets` guarantees it is large enough
+        let buckets = unsafe { &*r.load(DIR, &self.buckets) };
+        let dummy = buckets.slots[bucket].load(OSC);
+        if !dummy.is_null() {
+            return dummy;
+        }
+
+        // the parent bucket is the bucket index with its most significant bit cleared
+        let parent = bucket & !((bucket + 1).next_power_of_two() >> 1);
+        let start = self.bucket(r, parent);
+        let dummy = self.list.insert_dummy(r, start, bucket.reverse_bits());
+
+        // initializing the parent may have protected a newer directory in place of the one above,
+        // so load it again; directories only grow, so it is large enough too. if the directory is
+        // replaced after this, the store is lost and the next operation on the bucket will find
+        // the dummy node in the list again.
+        let buckets = unsafe { &*r.load(DIR, &self.buckets) };
+        buckets.slots[bucket].store(dummy, OSC);
+        dummy
+    }
+
+    /// Returns the dummy node of the bucket `hash` belongs to, and the split-order key of `hash`.
+    fn start<R>(&self, r: &mut R, hash: usize) -> (*mut Node<K, V>, usize)
+    where
+        R: Reclaimer<K, V>,
+    {
+        let (index, hash) = Self::locate(hash, self.nbuckets());
+        (self.bucket(r, index), hash)
+    }
+}
+
+impl<K, V> Table<K, V>
+where
+    K: Eq,
+{
+    /// Inserts a key-value pair, or replaces the value if the key is already present.
+    ///
+    /// Returns the replaced value, which has been retired.
+    pub(crate) fn insert<R>(&self, r: &mut R, hash: usize, key: K, value: V) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        let ret = self.list.insert(r, start, hash, key, value);
+
+        if ret.is_none() {
+            self.nitems.fetch_add(1, OSC);
+            self.maybe_grow(r);
+        }
+
+        ret
+    }
+
+    /// Inserts a key with the value returned by `make`, unless the key is already present.
+    ///
+    /// Returns the inserted value if the key was inserted. Otherwise, returns the value already in
+    /// the table, along with the value `make` produced if it was called before another thread
+    /// inserted the key.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn insert_new<R, F>(
+        &self,
+        r: &mut R,
+        hash: usize,
+        key: K,
+        make: F,
+    ) -> Result<*mut V, (*mut V, Option<V>)>
+    where
+        R: Reclaimer<K, V>,
+        F: FnOnce() -> V,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        let ret = self.list.insert_new(r, start, hash, key, make);
+
+        if ret.is_ok() {
+            self.nitems.fetch_add(1, OSC);
+            self.maybe_grow(r);
+        }
+
+        ret
+    }
+
+    /// Returns the value of a key, if it is present.
+    pub(crate) fn get<R, Q>(&self, r: &mut R, hash: usize, key: &Q) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        self.list.get(r, start, hash, key)
+    }
+
+    /// Replaces the value of a key with the value `f` returns for it, unless `f` returns `None`.
+    ///
+    /// Returns the new value. If nothing was replaced, returns the current value, if any, instead.
+    pub(crate) fn update<R, Q, F>(
+        &self,
+        r: &mut R,
+        hash: usize,
+        key: &Q,
+        f: F,
+    ) -> Result<*mut V, Option<*mut V>>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        F: FnMut(&V) -> Option<V>,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        self.list.update(r, start, hash, key, f)
+    }
+
+    /// Sets the value of a key to `f` applied to its current value, inserting or removing the key
+    /// as needed, and returns the new value.
+    pub(crate) fn compute<R, F>(&self, r: &mut R, hash: usize, key: K, f: F) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+        F: FnMut(Option<&V>) -> Option<V>,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        let (old, new) = self.list.compute(r, start, hash, key, f);
+
+        match (old, new) {
+            (None, Some(_)) => {
+                self.nitems.fetch_add(1, OSC);
+                self.maybe_grow(r);
+            }
+            (Some(_), None) => {
+                self.nitems.fetch_sub(1, OSC);
+            }
+            _ => {}
+        }
+
+        new
+    }
+
+    /// Removes a key if `pred` holds for its value, and returns the removed value.
+    pub(crate) fn remove<R, Q, F>(&self, r: &mut R, hash: usize, key: &Q, pred: F) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+        F: FnMut(&V) -> bool,
+    {
+        let (start, hash) = self.start(r, hash);
+
+        let ret = self.list.delete(r, start, hash, key, pred);
+
+        if ret.is_some() {
+            self.nitems.fetch_sub(1, OSC);
+        }
+
+        ret
+    }
+}
+
+impl<K, V> Table<K, V> {
+    /// Removes a key that has not been looked up recently, and returns its value.
+    ///
+    /// This is the [CLOCK](https://en.wikipedia.org/wiki/Page_replacement_algorithm#Clock)
+    /// approximation of least-recently-used eviction. A hand that all threads share sweeps over
+    /// the buckets in turn. It clears the reference bit of each key it passes, and it removes the
+    /// first key whose bit is still clear from the last time it passed. Each key's bit is set
+    /// again when an operation finds the key.
+    ///
+    /// Returns `None` if the hand makes two full sweeps without finding a key to remove, which
+    /// only happens if the table is empty, or other threads keep finding every key.
+    pub(crate) fn evict<R>(&self, r: &mut R) -> Option<*mut V>
+    where
+        R: Reclaimer<K, V>,
+    {
+        for _ in 0..2 * self.nbuckets() {
+            // the table may have grown since the hand was last moved, so wrap it around the
+            // current number of buckets
+            let index = self.hand.fetch_add(1, OSC) & (self.nbuckets() - 1);
+            let start = self.bucket(r, index);
+            if let Some(val) = self.list.evict(r, start) {
+                self.nitems.fetch_sub(1, OSC);
+                return Some(val);
+            }
+        }
+        None
+    }
+
+    /// Walks the whole table, and reports how its keys are spread over its buckets.
+    ///
+    /// Keys that are inserted or removed during the walk may or may not be counted.
+    pub(crate) fn load_report<R>(&self, r: &mut R) -> LoadReport
+    where
+        R: Reclaimer<K, V>,
+    {
+        let nbuckets = self.nbuckets();
+        let mut chains = vec![0; nbuckets];
+        let mut deleted_nodes = 0;
+
+        let mut cursor = Cursor::new(self);
+        // everything the cursor returns stays protected until the next call
+        while let Some(node) = unsafe { cursor.advance(self, r) } {
+            let n = unsafe { &*node };
+            if n.key.is_none() {
+                // a dummy node
+                continue;
+            }
+            if LinkedList::value(r, node).is_null() {
+                deleted_nodes += 1;
+                continue;
+            }
+            chains[n.hash.reverse_bits() & (nbuckets - 1)] += 1;
+        }
+
+        let longest_chain = chains.iter().cloned().max().unwrap_or(0);
+        let mut histogram = vec![0; longest_chain + 1];
+        for &chain in &chains {
+            histogram[chain] += 1;
+        }
+
+        LoadReport {
+            buckets: nbuckets,
+            empty_buckets: histogram[0],
+            histogram,
+            longest_chain,
+            deleted_nodes,
+        }
+    }
+}
+
+impl<K, V> Drop for Table<K, V> {
+    fn drop(&mut self) {
+        // replaced directories have been retired by the threads that replaced them
+        drop(unsafe { Box::from_raw(self.buckets.load(OSC)) });
+    }
+}
+
+/// A position in the list of a [`Table`], for iterating over its entries.
+///
+/// The node the cursor is at stays protected in [`ANCHOR`] between calls to [`Cursor::next`].
+pub(crate) struct Cursor<K, V> {
+    node: *mut Node<K, V>,
+    /// The nodes that have been visited in the current run of nodes with equal hashes.
+    run: Vec<*mut Node<K, V>>,
+    run_hash: Option<usize>,
+}
+
+impl<K, V> Cursor<K, V> {
+    /// Returns a cursor at the start of `table`.
+    pub(crate) fn new(table: &Table<K, V>) -> Self {
+        // the head is a dummy node, so it does not need protecting
+        Cursor {
+            node: table.list.head(),
+            run: Vec::new(),
+            run_hash: None,
+        }
+    }
+
+    /// Advances to the next entry of `table`, and returns its key and value.
+    ///
+    /// # Safety
+    ///
+    /// The cursor must have been created for `table`, and `r` must have been in the same critical
+    /// section since the previous call. The returned references are only valid until the next
+    /// call, or until `r` leaves its critical section.
+    pub(crate) unsafe fn next<'a, R>(
+        &mut self,
+        table: &'a Table<K, V>,
+        r: &mut R,
+    ) -> Option<(&'a K, &'a V)>
+    where
+        R: Reclaimer<K, V>,
+    {
+        loop {
+            let node = self.advance(table, r)?;
+            if let Some(key) = (*node).key.as_ref() {
+                let val = LinkedList::value(r, node);
+                if !val.is_null() {
+                    return Some((key, &*val));
+                }
+            }
+        }
+    }
+
+    /// Advances to the next node of `table`, and returns it, protected in [`ANCHOR`]. Unlike
+    /// [`Cursor::next`], this also stops at dummy nodes, and at nodes that have been removed but
+    /// are still linked into the list.
+    ///
+    /// # Safety
+    ///
+    /// See [`Cursor::next`]. The returned node is only valid until the next call, or until `r`
+    /// leaves its critical section.
+    unsafe fn advance<R>(&mut self, table: &Table<K, V>, r: &mut R) -> Option<*mut Node<K, V>>
+    where
+        R: Reclaimer<K, V>,
+    {
+        loop {
+            let next = match table.list.successor(r, self.node) {
+                Some(next) => next,
+                None => {
+                    // the node we are at has been removed, so its successor may be unlinked and
+                    // freed at any time. look up where the node was in the list instead, starting
+                    // from the bucket it belongs to.
+                    let hash = (*self.node).hash;
+                    let index = hash.reverse_bits() & (table.nbuckets() - 1);
+                    let start = table.bucket(r, index);
+                    table.list.search(r, start, |n| n.hash >= hash).1
+                }
+            };
+            if next.is_null() {
+                return None;
+            }
+
+            // `next` is protected until the next search, so it can be moved over safely
+            r.protect(ANCHOR, next);
+            self.node = next;
+
+            // nodes with equal hashes are in no particular order, so finding a removed node's
+            // place again means going back to the start of its run. skip the nodes of the run
+            // that have been visited already.
+            let n = &*next;
+            if self.run_hash != Some(n.hash) {
+                self.run.clear();
+                self.run_hash = Some(n.hash);
+            } else if self.run.contains(&next) {
+                continue;
+            }
+            self.run.push(next);
+
+            return Some(next);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Fixtures shared by the tests of the maps.
+
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Hashes every key to the same value.
+#[derive(Default)]
+pub(crate) struct ConstantHasher;
+
+impl Hasher for ConstantHasher {
+    fn finish(&self) -> u64 {
+        42
+    }
+
+    fn write(&mut self, _: &[u8]) {}
+}
+
+/// Keeps track of which [`Tracked`] instances are alive.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    live: Mutex<HashSet<usize>>,
+    next: AtomicUsize,
+}
+
+impl Tracker {
+    /// Returns the number of instances that are alive.
+    pub(crate) fn live(&self) -> usize {
+        self.live.lock().unwrap().len()
+    }
+}
+
+/// An instance that records in its `Tracker` whether it is alive, to catch leaks and double
+/// drops. All instances compare equal, so that they can be attached to keys.
+pub(crate) struct Tracked(Arc<Tracker>, usize);
+
+impl Tracked {
+    pub(crate) fn new(tracker: &Arc<Tracker>) -> Self {
+        let id = tracker.next.fetch_add(1, Ordering::SeqCst);
+        tracker.live.lock().unwrap().insert(id);
+        Tracked(Arc::clone(tracker), id)
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Self {
+        Tracked::new(&self.0)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        assert!(self.0.live.lock().unwrap().remove(&self.1), "dropped twice");
+    }
+}
+
+impl PartialEq for Tracked {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Tracked {}
+
+impl Hash for Tracked {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
@@ -0,0 +1,94 @@
+//! Checks that short-lived handles to a `manual::Map` do not leak memory.
+//!
+//! This counts allocations with its own global allocator, which would count the allocations of
+//! every other test in the same binary too, so it lives in a binary of its own, with a single test.
+
+extern crate concache;
+
+use concache::manual::Map;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::thread;
+
+/// An allocator that keeps track of the bytes allocated by threads inside [`counted`].
+struct CountingAlloc;
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count(bytes: isize) {
+    if COUNTING.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATED.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Runs `f` with allocations on this thread counted in `ALLOCATED`.
+fn counted<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    COUNTING.with(|c| c.set(true));
+    let ret = f();
+    COUNTING.with(|c| c.set(false));
+    ret
+}
+
+#[test]
+fn handle_drop() {
+    let mut handle = counted(|| Map::with_capacity(16));
+    let nthreads = 4;
+
+    let mut allocated = Vec::new();
+    for _ in 0..10 {
+        let threads: Vec<_> = (0..nthreads)
+            .map(|t| {
+                // spawning and joining threads allocates too, so only count the map's work
+                let mut new_handle = counted(|| handle.clone());
+                thread::spawn(move || {
+                    counted(move || {
+                        for i in 0..100 {
+                            let key = t * 100 + i;
+                            new_handle.insert(key, vec![key; 8]);
+                            new_handle.remove(&key);
+                        }
+                        drop(new_handle);
+                    })
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        // a handle that is dropped while another is in a critical section leaves some of its
+        // garbage with the map, for the next cleanup to free
+        counted(|| handle.flush());
+        allocated.push(ALLOCATED.load(Ordering::SeqCst));
+    }
+
+    // once the table has warmed up, short-lived handles leave nothing behind
+    assert!(
+        allocated[1..].iter().all(|&a| a == allocated[1]),
+        "{:?}",
+        allocated
+    );
+    assert_eq!(handle.stats().handles, 1);
+}